@@ -1,14 +1,60 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use serde::Deserialize;
 use snafu::ResultExt;
 
 use crate::database::DatabaseConfig;
-use crate::error::{ApplicationError, ConfigLoadSnafu};
+use crate::error::{ApplicationError, ConfigLoadSnafu, ReadSecretSnafu};
+use crate::maintenance::MaintenanceConfig;
+use crate::sink::SinkConfig;
 use crate::youtube::YouTubeConfig;
 
+/// Suffix of environment variables that point to a file containing the actual value.
+const FILE_SUFFIX: &str = "_FILE";
+
+/// Variables that may be read from a file instead of being passed directly.
+const SECRETS: &[&str] = &["SURREAL_PASS", "INFLUX_TOKEN"];
+
 pub fn load() -> Result<Config, ApplicationError> {
-    envy::from_env().context(ConfigLoadSnafu)
+    let variables = resolve_secrets(std::env::vars())?;
+    let config: Config = envy::from_iter(variables).context(ConfigLoadSnafu)?;
+
+    config.maintenance.window()?;
+    Ok(config)
+}
+
+/// Replace the `<NAME>_FILE` variable of each secret with `<NAME>`, reading its value from the referenced file.
+///
+/// This follows the Docker/Kubernetes secrets convention so that credentials don't have to be passed as plain environment variables.
+/// A variable that is set directly takes precedence over its `_FILE` counterpart.
+fn resolve_secrets(
+    variables: impl IntoIterator<Item = (String, String)>,
+) -> Result<HashMap<String, String>, ApplicationError> {
+    let mut variables: HashMap<_, _> = variables.into_iter().collect();
+
+    for name in SECRETS {
+        let key = format!("{name}{FILE_SUFFIX}");
+
+        let Some(path) = variables.remove(&key) else {
+            continue;
+        };
+
+        if variables.contains_key(*name) {
+            continue;
+        }
+
+        let path = PathBuf::from(path);
+        let secret = std::fs::read_to_string(&path).context(ReadSecretSnafu {
+            variable: key.clone(),
+            path,
+        })?;
+
+        variables.insert(name.to_string(), secret.trim_end().to_string());
+    }
+
+    Ok(variables)
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -19,6 +65,10 @@ pub struct Config {
     pub database: DatabaseConfig,
     #[serde(flatten)]
     pub youtube: YouTubeConfig,
+    #[serde(flatten)]
+    pub sink: SinkConfig,
+    #[serde(flatten)]
+    pub maintenance: MaintenanceConfig,
 
     #[serde(default = "defaults::log_dir")]
     pub log_dir: String,
@@ -29,3 +79,62 @@ mod defaults {
         "logs".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("kitsune-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn variables(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn secret_is_read_from_file_without_trailing_newline() {
+        let path = secret_file("read", "hunter2\n");
+        let resolved =
+            resolve_secrets(variables(&[("SURREAL_PASS_FILE", path.to_str().unwrap())])).unwrap();
+
+        assert_eq!(
+            resolved.get("SURREAL_PASS").map(String::as_str),
+            Some("hunter2")
+        );
+        assert!(!resolved.contains_key("SURREAL_PASS_FILE"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn direct_variable_takes_precedence() {
+        let path = secret_file("precedence", "from-file");
+        let resolved = resolve_secrets(variables(&[
+            ("INFLUX_TOKEN", "direct"),
+            ("INFLUX_TOKEN_FILE", path.to_str().unwrap()),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            resolved.get("INFLUX_TOKEN").map(String::as_str),
+            Some("direct")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn other_file_variables_are_left_alone() {
+        let resolved = resolve_secrets(variables(&[("LOG_FILE", "/does/not/exist")])).unwrap();
+
+        assert_eq!(
+            resolved.get("LOG_FILE").map(String::as_str),
+            Some("/does/not/exist")
+        );
+        assert!(!resolved.contains_key("LOG"));
+    }
+}
@@ -56,6 +56,48 @@ impl<T> TryFrom<Vec<T>> for Only<T> {
     }
 }
 
+/// Query result extractor that skips rows which can't be deserialized instead of failing the whole query.
+///
+/// Use this where one malformed record must not take every other record down with it.
+#[derive(Debug)]
+pub struct Valid<T>(pub Vec<T>);
+
+impl<T: DeserializeOwned> Valid<T> {
+    fn from_rows(rows: Vec<serde_json::Value>) -> Self {
+        let valid = rows
+            .into_iter()
+            .filter_map(|row| {
+                let id = row.get("id").cloned().unwrap_or_default();
+                serde_json::from_value(row)
+                    .map_err(|error| tracing::error!(%id, %error, "skipping malformed record"))
+                    .ok()
+            })
+            .collect();
+
+        Valid(valid)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Valid<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Valid::from_rows)
+    }
+}
+
+impl<T: DeserializeOwned> QueryResult<Valid<T>> for usize {
+    fn query_result(self, response: &mut surrealdb::Response) -> super::Result<Valid<T>> {
+        self.query_result(response).map(Valid::from_rows)
+    }
+}
+
+impl<T> Deref for Valid<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl<T> Deref for Only<T> {
     type Target = T;
 
@@ -63,3 +105,23 @@ impl<T> Deref for Only<T> {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::time::Daily;
+
+    #[test]
+    fn malformed_rows_are_skipped() {
+        let rows = serde_json::json!([
+            { "at": "23:59:00", "timezone": "Asia/Tokyo" },
+            { "at": "23:59:00", "timezone": "Asia/Tokio" },
+            { "at": "25:00:00", "timezone": "Asia/Tokyo" },
+        ]);
+
+        let Valid(schedules) = serde_json::from_value::<Valid<Daily>>(rows).unwrap();
+        assert_eq!(schedules.len(), 1);
+        assert_eq!(schedules[0].timezone, chrono_tz::Asia::Tokyo);
+    }
+}
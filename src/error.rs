@@ -1,8 +1,10 @@
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use snafu::{Location, Snafu};
 
 use crate::database::DatabaseError;
+use crate::time::Timestamp;
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
@@ -14,6 +16,23 @@ pub enum ApplicationError {
         location: Location,
     },
 
+    /// The maintenance window has to end after it starts
+    MaintenanceWindow {
+        start: Timestamp,
+        end: Timestamp,
+        #[snafu(implicit)]
+        location: Location,
+    },
+
+    /// Could not read the secret file referenced by a `_FILE` variable
+    ReadSecret {
+        variable: String,
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+
     ConnectDatabase {
         source: DatabaseError,
         #[snafu(implicit)]
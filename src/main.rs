@@ -4,12 +4,16 @@ mod config;
 mod database;
 mod error;
 mod logger;
+mod maintenance;
 mod model;
+mod sink;
+mod systemd;
 mod time;
 mod tracker;
 mod youtube;
 
 use error::ApplicationError;
+use tokio::select;
 
 #[tokio::main]
 async fn main() -> Result<(), ApplicationError> {
@@ -20,7 +24,28 @@ async fn main() -> Result<(), ApplicationError> {
     let _guard = logger::init(&config)?;
 
     database::connect(&config.database).await?;
+    sink::connect(&config.sink);
+    maintenance::schedule(&config.maintenance);
     let youtube = youtube::connect(&config.youtube).await;
 
-    tracker::watcher(youtube).await
+    select! {
+        result = tracker::watcher(youtube) => result,
+        _ = shutdown_signal() => {
+            tracing::info!("shutting down");
+            systemd::stopping();
+            sink::shutdown().await;
+
+            Ok(())
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("install SIGTERM handler");
+
+    select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
 }
@@ -0,0 +1,97 @@
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use snafu::ensure;
+
+use crate::error::{ApplicationError, MaintenanceWindowSnafu};
+use crate::time::Timestamp;
+
+/// Optional window during which trackers stop fetching, e.g. while SurrealDB is being upgraded.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaintenanceConfig {
+    #[serde(rename = "maintenance_start")]
+    start: Option<Timestamp>,
+    #[serde(rename = "maintenance_end")]
+    end: Option<Timestamp>,
+}
+
+impl MaintenanceConfig {
+    /// The configured window, if both ends are set. It has to end after it starts.
+    pub fn window(&self) -> Result<Option<Window>, ApplicationError> {
+        let (Some(start), Some(end)) = (self.start, self.end) else {
+            return Ok(None);
+        };
+
+        ensure!(start < end, MaintenanceWindowSnafu { start, end });
+        Ok(Some(Window { start, end }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+impl Window {
+    pub fn contains(&self, timestamp: Timestamp) -> bool {
+        self.start <= timestamp && timestamp < self.end
+    }
+}
+
+static WINDOW: OnceCell<Window> = OnceCell::new();
+
+pub fn schedule(config: &MaintenanceConfig) {
+    // the window was already validated when the config was loaded
+    let Ok(Some(window)) = config.window() else {
+        return;
+    };
+
+    tracing::info!(%window.start, %window.end, "scheduled maintenance window");
+    WINDOW
+        .set(window)
+        .expect("maintenance is only scheduled once");
+}
+
+/// The maintenance window `timestamp` falls into, if any.
+pub fn during(timestamp: Timestamp) -> Option<Window> {
+    WINDOW
+        .get()
+        .copied()
+        .filter(|window| window.contains(timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn window_excludes_its_end() {
+        let window = Window {
+            start: Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 7, 1, 1, 0, 0).unwrap(),
+        };
+
+        assert!(window.contains(window.start));
+        assert!(!window.contains(window.end));
+    }
+
+    #[test]
+    fn window_has_to_end_after_it_starts() {
+        let start = Utc.with_ymd_and_hms(2024, 7, 1, 1, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+
+        let config = MaintenanceConfig {
+            start: Some(start),
+            end: Some(end),
+        };
+        assert!(config.window().is_err());
+
+        let config = MaintenanceConfig {
+            start: Some(end),
+            end: Some(start),
+        };
+        assert!(config.window().unwrap().is_some());
+    }
+}
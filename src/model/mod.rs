@@ -1,32 +1,153 @@
-use query::Only;
+use query::{Only, Valid};
 use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
+use tracing::Instrument;
+use url::Url;
 
 use crate::database::{database, query, DatabaseError};
-use crate::time::{Interval, Timestamp};
+use crate::time::{Daily, Interval, Timestamp};
+use crate::youtube::{Stats, UploadInfo};
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Tracker {
     pub id: Thing,
     pub created_at: Timestamp,
     pub stopped_at: Option<Timestamp>,
+    #[serde(default)]
+    pub status: TrackerStatus,
+    pub stop_reason: Option<String>,
+    pub summary: Option<Summary>,
+    /// why this video is being tracked, e.g. the fan project it belongs to
+    pub notes: Option<String>,
+    /// related pages, e.g. the celebration stream or the project doc
+    #[serde(default)]
+    pub links: Vec<Url>,
     #[serde(flatten)]
     pub data: TrackerData,
 }
 
 impl Tracker {
     pub fn is_stopped(&self) -> bool {
-        self.stopped_at.is_some()
+        !self.status.is_active()
     }
 
     query! {
-        all_active() -> Vec<Tracker> where
-            "SELECT * FROM trackers WHERE !stopped_at ORDER BY created_at DESC"
+        all_active() -> Valid<Tracker> where
+            "SELECT * FROM trackers WHERE status INSIDE ['scheduled', 'running'] ORDER BY created_at DESC"
     }
 
     query! {
-        stop(id: &Thing) -> Only<Tracker> where
-            "UPDATE $id SET stopped_at = time::now()"
+        statuses() -> Vec<TrackerState> where
+            "SELECT id, status FROM trackers"
+    }
+
+    query! {
+        start(id: &Thing) -> Option<Tracker> where
+            "UPDATE $id SET status = 'running' WHERE status = 'scheduled'"
+    }
+
+    query! {
+        reschedule(id: &Thing, scheduled_on: Timestamp) -> Option<Tracker> where
+            "UPDATE $id SET scheduled_on = <datetime> $scheduled_on WHERE status = 'scheduled'"
+    }
+
+    query! {
+        stop(id: &Thing, status: TrackerStatus, reason: String, summary: Option<Summary>) -> Only<Tracker> where
+            "UPDATE $id SET status = $status, stop_reason = $reason, summary = $summary, stopped_at = time::now()"
+    }
+}
+
+/// The status of a tracker, without the rest of its data.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct TrackerState {
+    pub id: Thing,
+    #[serde(default)]
+    pub status: TrackerStatus,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackerEventKind {
+    Created,
+    Started,
+    Rescheduled,
+    /// settings other than the schedule changed
+    Updated,
+    Paused,
+    Resumed,
+    Completed,
+    Failed,
+    Restricted,
+    Cancelled,
+    Deleted,
+}
+
+impl TrackerEventKind {
+    /// the event for a tracker that stopped with the given status, or was deleted if there is none.
+    pub fn stopped(status: Option<TrackerStatus>) -> Option<Self> {
+        match status {
+            None => Some(TrackerEventKind::Deleted),
+            Some(TrackerStatus::Paused) => Some(TrackerEventKind::Paused),
+            Some(TrackerStatus::Completed) => Some(TrackerEventKind::Completed),
+            Some(TrackerStatus::Failed) => Some(TrackerEventKind::Failed),
+            Some(TrackerStatus::Restricted) => Some(TrackerEventKind::Restricted),
+            Some(TrackerStatus::Cancelled) => Some(TrackerEventKind::Cancelled),
+            Some(TrackerStatus::Scheduled | TrackerStatus::Running) => None,
+        }
+    }
+}
+
+/// An append-only record of a tracker's lifecycle transition.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct TrackerEvent {
+    pub id: Thing,
+    pub tracker: Thing,
+    pub kind: TrackerEventKind,
+    pub created_at: Timestamp,
+}
+
+impl TrackerEvent {
+    query! {
+        append(tracker: &Thing, kind: TrackerEventKind) -> Only<TrackerEvent> where
+            "CREATE tracker_events SET tracker = $tracker, kind = $kind"
+    }
+}
+
+/// Final figures of a tracker, computed once when it stops.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Summary {
+    pub views: u64,
+    pub likes: u64,
+    pub samples: u64,
+    /// time between the first and the last sample
+    pub duration: Interval,
+    pub peak_views_per_hour: Option<u64>,
+    /// hours watched over the tracked views, assuming every view watched the whole video
+    pub watch_hours: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackerStatus {
+    /// waiting for `scheduled_on` to come around
+    #[default]
+    Scheduled,
+    Running,
+    /// not recording until it is set back to scheduled or running
+    Paused,
+    /// reached its milestone
+    Completed,
+    /// stopped because its video can no longer be tracked
+    Failed,
+    /// stopped because its video is members-only, age-restricted or region-blocked
+    Restricted,
+    /// stopped by hand
+    Cancelled,
+}
+
+impl TrackerStatus {
+    pub fn is_active(self) -> bool {
+        matches!(self, TrackerStatus::Scheduled | TrackerStatus::Running)
     }
 }
 
@@ -35,13 +156,40 @@ pub struct TrackerData {
     pub video: String,
     pub scheduled_on: Timestamp,
     pub interval: Interval,
+    /// record on a wall-clock time every day instead of every `interval`
+    pub daily: Option<Daily>,
     pub milestone: Option<u64>,
+    /// after the first day, confirm the previous record instead of inserting an identical one
+    #[serde(default)]
+    pub skip_unchanged: bool,
+    /// fetch from this Invidious instance instead of the configured one
+    pub instance: Option<String>,
 }
 
 impl TrackerData {
     pub fn exceed_milestone(&self, views: u64) -> bool {
         self.milestone.map_or(false, |milestone| views >= milestone)
     }
+
+    /// Time between two ticks: a day for daily trackers, whose `interval` isn't used, `interval` otherwise.
+    pub fn period(&self) -> std::time::Duration {
+        match self.daily {
+            Some(_) => std::time::Duration::from_secs(24 * 60 * 60),
+            None => *self.interval,
+        }
+    }
+
+    /// Whether `other` ticks at the same instants as this tracker.
+    pub fn same_schedule(&self, other: &TrackerData) -> bool {
+        self.scheduled_on == other.scheduled_on
+            && self.interval == other.interval
+            && self.daily == other.daily
+    }
+
+    /// The instant 24 hours after the tracker was scheduled to start.
+    pub fn first_day(&self) -> Timestamp {
+        self.scheduled_on + chrono::Duration::days(1)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -50,12 +198,174 @@ pub struct Record {
     pub tracker: Thing,
     pub views: u64,
     pub likes: u64,
+    /// the instant this sample was scheduled for, as opposed to when it was inserted
+    pub tick_at: Option<Timestamp>,
+    /// the sample looked implausible compared to the previous one, see [AnomalyKind]
+    #[serde(default)]
+    pub flagged: bool,
+    /// the last tick that observed the exact same stats, when identical samples are skipped
+    pub confirmed_at: Option<Timestamp>,
+    /// how long after `tick_at` the stats were fetched
+    pub lateness_ms: Option<u64>,
+    /// hours watched since the tracker's first sample, see [Summary::watch_hours]
+    pub watch_hours: Option<u64>,
+    pub created_at: Timestamp,
 }
 
 impl Record {
+    /// When the stats of this record were fetched, as opposed to the tick they belong to.
+    pub fn fetched_at(&self) -> Timestamp {
+        let lateness = chrono::Duration::milliseconds(self.lateness_ms.unwrap_or_default() as i64);
+        self.tick_at.unwrap_or(self.created_at) + lateness
+    }
+
+    /// Whether a new sample has the exact same stats as this record.
+    pub fn is_unchanged(&self, stats: &Stats) -> bool {
+        self.views == stats.views && self.likes == stats.likes
+    }
+
+    // keyed by tracker and tick, so retrying a tick overwrites its record instead of adding another one
+    query! {
+        create(tracker: &Thing, views: u64, likes: u64, tick_at: Timestamp, lateness_ms: Option<u64>, watch_hours: Option<u64>, flagged: bool) -> Only<Record> where
+            "UPDATE type::thing('records', [$tracker, <datetime> $tick_at]) SET tracker = $tracker, views = $views, likes = $likes, tick_at = <datetime> $tick_at, lateness_ms = $lateness_ms, watch_hours = $watch_hours, flagged = $flagged"
+    }
+
+    query! {
+        first(tracker: &Thing) -> Option<Record> where
+            "SELECT * FROM records WHERE tracker = $tracker AND !flagged ORDER BY created_at ASC LIMIT 1"
+    }
+
+    query! {
+        all(tracker: &Thing, include_flagged: bool) -> Vec<Record> where
+            "SELECT * FROM records WHERE tracker = $tracker AND ($include_flagged OR !flagged) ORDER BY created_at ASC"
+    }
+
+    query! {
+        confirm(record: &Thing, timestamp: Timestamp) -> Only<Record> where
+            "UPDATE $record SET confirmed_at = <datetime> $timestamp"
+    }
+
+    query! {
+        latest(tracker: &Thing) -> Option<Record> where
+            "SELECT * FROM records WHERE tracker = $tracker AND !flagged ORDER BY created_at DESC LIMIT 1"
+    }
+}
+
+/// Cached metadata of a tracked video, keyed by its video id.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Video {
+    pub id: Thing,
+    #[serde(flatten)]
+    pub info: UploadInfo,
+    pub refreshed_at: Timestamp,
+}
+
+impl Video {
+    query! {
+        find(video: &str) -> Option<Video> where
+            "SELECT * FROM type::thing('videos', $video)"
+    }
+
+    query! {
+        save(video: &str, info: UploadInfo) -> Only<Video> where
+            "UPDATE type::thing('videos', $video) CONTENT {
+                title: $info.title,
+                channel: $info.channel,
+                channel_id: $info.channel_id,
+                duration: $info.duration,
+                published_at: <datetime> $info.published_at,
+                premiere_at: IF $info.premiere_at THEN <datetime> $info.premiere_at END,
+                thumbnail: $info.thumbnail
+            }"
+    }
+}
+
+/// Best figures ever observed for a video, across all of its trackers.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Peak {
+    pub id: Thing,
+    pub video: String,
+    pub views_per_hour: Option<u64>,
+    pub views_per_hour_at: Option<Timestamp>,
+    pub million_after: Option<Interval>,
+}
+
+impl Peak {
+    query! {
+        views_per_hour(video: &str, views_per_hour: u64, timestamp: Timestamp) -> Only<Peak> where
+            "UPDATE type::thing('peaks', $video) SET
+                video = $video,
+                views_per_hour_at = IF views_per_hour < $views_per_hour THEN <datetime> $timestamp ELSE views_per_hour_at END,
+                views_per_hour = IF views_per_hour < $views_per_hour THEN $views_per_hour ELSE views_per_hour END"
+    }
+
+    query! {
+        million(video: &str, after: Interval) -> Option<Peak> where
+            "UPDATE type::thing('peaks', $video) SET video = $video, million_after = $after WHERE million_after = NONE"
+    }
+}
+
+/// Counters describing how reliably a tracker has been ticking.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct TrackerHealth {
+    pub id: Thing,
+    pub tracker: Thing,
+    pub missed_ticks: u64,
+    pub last_missed_at: Option<Timestamp>,
+}
+
+impl TrackerHealth {
     query! {
-        create(tracker: &Thing, views: u64, likes: u64, created_at: Timestamp) -> Only<Record> where
-            "CREATE records SET tracker = $tracker, views = $views, likes = $likes, created_at = $created_at"
+        missed_ticks(tracker: &Thing, missed: u64, timestamp: Timestamp) -> Only<TrackerHealth> where
+            "UPDATE type::thing('tracker_health', meta::id($tracker)) SET tracker = $tracker, missed_ticks += $missed, last_missed_at = <datetime> $timestamp"
+    }
+}
+
+/// Views and likes of a tracker 24 hours after it was scheduled.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct FirstDayReport {
+    pub id: Thing,
+    pub tracker: Thing,
+    pub views: u64,
+    pub likes: u64,
+    pub recorded_at: Timestamp,
+}
+
+impl FirstDayReport {
+    query! {
+        find(tracker: &Thing) -> Option<FirstDayReport> where
+            "SELECT * FROM first_day_reports WHERE tracker = $tracker LIMIT 1"
+    }
+
+    query! {
+        create(tracker: &Thing, views: u64, likes: u64, recorded_at: Timestamp) -> Only<FirstDayReport> where
+            "CREATE first_day_reports SET tracker = $tracker, views = $views, likes = $likes, recorded_at = <datetime> $recorded_at"
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// views went down, usually because YouTube audited the view count
+    Drop,
+    /// views increased implausibly fast compared to the previous tick
+    Spike,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Anomaly {
+    pub id: Thing,
+    pub tracker: Thing,
+    pub kind: AnomalyKind,
+    pub previous: u64,
+    pub views: u64,
+    pub created_at: Timestamp,
+}
+
+impl Anomaly {
+    query! {
+        create(tracker: &Thing, kind: AnomalyKind, previous: u64, views: u64, created_at: Timestamp) -> Only<Anomaly> where
+            "CREATE anomalies SET tracker = $tracker, kind = $kind, previous = $previous, views = $views, created_at = <datetime> $created_at"
     }
 }
 
@@ -67,11 +377,78 @@ pub struct StaggeredRecord {
     pub created_at: Timestamp,
 }
 
+/// How far behind schedule stats were fetched, see [Record::lateness_ms].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Accuracy {
+    /// the tracker this report is for, none for the overall report
+    #[serde(default)]
+    pub tracker: Option<Thing>,
+    pub samples: u64,
+    pub mean_lateness_ms: f64,
+    pub max_lateness_ms: u64,
+}
+
+impl Accuracy {
+    query! {
+        tracker(tracker: &Thing) -> Option<Accuracy> where
+            "SELECT count() AS samples, math::mean(lateness_ms) AS mean_lateness_ms, math::max(lateness_ms) AS max_lateness_ms
+            FROM records WHERE tracker = $tracker AND lateness_ms != NONE GROUP ALL"
+    }
+
+    query! {
+        active() -> Vec<Accuracy> where
+            "SELECT tracker, count() AS samples, math::mean(lateness_ms) AS mean_lateness_ms, math::max(lateness_ms) AS max_lateness_ms
+            FROM records WHERE lateness_ms != NONE
+                AND tracker INSIDE (SELECT VALUE id FROM trackers WHERE status INSIDE ['scheduled', 'running'])
+            GROUP BY tracker"
+    }
+
+    query! {
+        overall() -> Option<Accuracy> where
+            "SELECT count() AS samples, math::mean(lateness_ms) AS mean_lateness_ms, math::max(lateness_ms) AS max_lateness_ms
+            FROM records WHERE lateness_ms != NONE GROUP ALL"
+    }
+}
+
+pub mod events {
+    use once_cell::sync::Lazy;
+    use tokio::sync::mpsc::{self, UnboundedSender};
+    use tracing::Span;
+
+    use super::*;
+
+    type Pending = (Thing, TrackerEventKind, Span);
+
+    /// A single writer, so that the events of a tracker are stored in the order they were appended.
+    static WRITER: Lazy<UnboundedSender<Pending>> = Lazy::new(|| {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Pending>();
+
+        tokio::spawn(async move {
+            while let Some((tracker, kind, span)) = rx.recv().await {
+                let write = TrackerEvent::append(&tracker, kind).instrument(span);
+
+                if let Err(error) = write.await {
+                    tracing::error!(%tracker, ?kind, %error, "could not append tracker event");
+                }
+            }
+        });
+
+        tx
+    });
+
+    pub fn append(tracker: Thing, kind: TrackerEventKind) {
+        if let Err(error) = WRITER.send((tracker, kind, Span::current())) {
+            let (tracker, kind, _) = error.0;
+            tracing::error!(%tracker, ?kind, "tracker event writer is no longer running");
+        }
+    }
+}
+
 pub mod log {
     use super::*;
 
     pub fn error(message: String, tracker: Thing) {
-        tokio::spawn(async move {
+        let write = async move {
             database()
                 .query("LET $log = (CREATE logs SET type = 'error', message = $message, created_at = time::now() RETURN *)")
                 .query("LET $log_id = $log.id")
@@ -80,6 +457,174 @@ pub mod log {
                 .bind(("tracker", tracker))
                 .await
                 .expect("executed surrealql query");
+        };
+
+        tokio::spawn(write.in_current_span());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    const SCHEMA: &str = include_str!("../../schema.surrealql");
+
+    #[test]
+    fn daily_trackers_tick_once_a_day() {
+        let mut data = TrackerData {
+            video: "dQw4w9WgXcQ".to_string(),
+            scheduled_on: Utc::now(),
+            interval: std::time::Duration::from_secs(600).into(),
+            daily: None,
+            milestone: None,
+            skip_unchanged: false,
+            instance: None,
+        };
+        assert_eq!(data.period(), std::time::Duration::from_secs(600));
+
+        data.daily = Some(Daily {
+            at: chrono::NaiveTime::from_hms_opt(23, 59, 0).unwrap(),
+            timezone: chrono_tz::Asia::Tokyo,
         });
+        assert_eq!(data.period(), std::time::Duration::from_secs(24 * 60 * 60));
+    }
+
+    /// Every write query has to pass the schema, e.g. timestamps bind as strings and need a `<datetime>` cast.
+    #[tokio::test]
+    async fn writes_match_schema() {
+        database().connect("mem://").await.unwrap();
+        database().use_ns("test").use_db("test").await.unwrap();
+        database()
+            .query(SCHEMA)
+            .await
+            .and_then(|response| response.check())
+            .expect("schema applies");
+
+        let tracker = Thing::from(("trackers", "test"));
+        let scheduled_on = Utc.with_ymd_and_hms(2024, 7, 1, 12, 0, 0).unwrap();
+        database()
+            .query("CREATE $tracker SET title = 'test', video = 'dQw4w9WgXcQ', scheduled_on = <datetime> $scheduled_on, interval = 1h")
+            .bind(("tracker", tracker.clone()))
+            .bind(("scheduled_on", scheduled_on))
+            .await
+            .and_then(|response| response.check())
+            .expect("tracker is created");
+
+        for (instance, valid) in [("https://yewtu.be", true), ("yewtu be", false)] {
+            let result = database()
+                .query("UPDATE $tracker SET instance = $instance")
+                .bind(("tracker", tracker.clone()))
+                .bind(("instance", instance))
+                .await
+                .and_then(|response| response.check());
+
+            assert_eq!(result.is_ok(), valid, "instance {instance:?}");
+        }
+
+        let scheduled_on = scheduled_on + Duration::hours(1);
+        let rescheduled = Tracker::reschedule(&tracker, scheduled_on).await.unwrap();
+        assert_eq!(
+            rescheduled.map(|tracker| tracker.data.scheduled_on),
+            Some(scheduled_on)
+        );
+        assert!(Tracker::start(&tracker).await.unwrap().is_some());
+        assert_eq!(Tracker::all_active().await.unwrap().len(), 1);
+        assert_eq!(
+            Tracker::statuses().await.unwrap(),
+            vec![TrackerState {
+                id: tracker.clone(),
+                status: TrackerStatus::Running,
+            }]
+        );
+        TrackerEvent::append(&tracker, TrackerEventKind::Started)
+            .await
+            .unwrap();
+
+        let tick_at = scheduled_on;
+        let Only(record) = Record::create(&tracker, 1_000, 10, tick_at, Some(250), None, false)
+            .await
+            .unwrap();
+        let Only(retried) = Record::create(&tracker, 1_200, 12, tick_at, Some(900), Some(1), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            record.id, retried.id,
+            "a retried tick should overwrite its record"
+        );
+        assert_eq!(Record::all(&tracker, true).await.unwrap().len(), 1);
+        assert_eq!(
+            Record::first(&tracker)
+                .await
+                .unwrap()
+                .and_then(|record| record.watch_hours),
+            Some(1)
+        );
+
+        let accuracy = Accuracy::tracker(&tracker).await.unwrap().unwrap();
+        assert_eq!((accuracy.samples, accuracy.max_lateness_ms), (1, 900));
+        assert_eq!(Accuracy::overall().await.unwrap(), Some(accuracy));
+        let active = Accuracy::active().await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].tracker.as_ref(), Some(&tracker));
+
+        let Only(confirmed) = Record::confirm(&record.id, tick_at + Duration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(confirmed.confirmed_at, Some(tick_at + Duration::hours(1)));
+        assert_eq!(
+            Record::latest(&tracker)
+                .await
+                .unwrap()
+                .map(|record| record.views),
+            Some(1_200)
+        );
+
+        let info = UploadInfo {
+            title: "test".to_string(),
+            channel: "channel".to_string(),
+            channel_id: "UC0".to_string(),
+            duration: std::time::Duration::from_secs(200).into(),
+            published_at: scheduled_on,
+            premiere_at: Some(scheduled_on),
+            thumbnail: None,
+        };
+        Video::save("dQw4w9WgXcQ", info).await.unwrap();
+        assert!(Video::find("dQw4w9WgXcQ").await.unwrap().is_some());
+
+        Peak::views_per_hour("dQw4w9WgXcQ", 200, tick_at)
+            .await
+            .unwrap();
+        Peak::million("dQw4w9WgXcQ", std::time::Duration::from_secs(3_600).into())
+            .await
+            .unwrap();
+        TrackerHealth::missed_ticks(&tracker, 2, tick_at)
+            .await
+            .unwrap();
+        FirstDayReport::create(&tracker, 1_200, 12, tick_at)
+            .await
+            .unwrap();
+        Anomaly::create(&tracker, AnomalyKind::Spike, 1_000, 1_200, tick_at)
+            .await
+            .unwrap();
+
+        let summary = Summary {
+            views: 1_200,
+            likes: 12,
+            samples: 1,
+            duration: std::time::Duration::ZERO.into(),
+            peak_views_per_hour: Some(200),
+            watch_hours: Some(66),
+        };
+        let Only(stopped) = Tracker::stop(
+            &tracker,
+            TrackerStatus::Completed,
+            "done".to_string(),
+            Some(summary),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stopped.status, TrackerStatus::Completed);
     }
 }
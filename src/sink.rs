@@ -0,0 +1,218 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+use tokio::select;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio_retry::strategy::{jitter, ExponentialBackoff};
+use tokio_retry::Retry;
+use url::Url;
+
+use crate::time::Timestamp;
+use crate::youtube::Stats;
+
+/// Maximum number of points written in a single request.
+const BATCH_SIZE: usize = 500;
+
+/// How long to wait for a batch to fill up before writing it anyway.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of points waiting to be written; newer points are dropped while InfluxDB is unreachable.
+const QUEUE_SIZE: usize = 10 * BATCH_SIZE;
+
+/// How long shutdown waits for the last points to be written.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Optional InfluxDB target that every recorded stats row is mirrored to.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SinkConfig {
+    /// write endpoint, e.g. `http://localhost:8086/api/v2/write?org=kitsune&bucket=stats`
+    #[serde(rename = "influx_url")]
+    url: Option<Url>,
+    #[serde(rename = "influx_token")]
+    token: Option<String>,
+}
+
+struct Sink {
+    points: Sender<String>,
+    writer: tokio::task::JoinHandle<()>,
+}
+
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+pub fn connect(config: &SinkConfig) {
+    let Some(url) = config.url.clone() else {
+        return;
+    };
+
+    tracing::info!(%url, "mirroring stats to influxdb");
+
+    let (points, rx) = mpsc::channel(QUEUE_SIZE);
+
+    let writer = Writer {
+        client: reqwest::Client::new(),
+        url,
+        token: config.token.clone(),
+    };
+
+    let writer = tokio::spawn(writer.run(rx));
+    let previous = SINK.lock().unwrap().replace(Sink { points, writer });
+    assert!(previous.is_none(), "sink is only connected once");
+}
+
+/// Stop accepting points and wait for the queued ones to be written, if a sink is configured.
+pub async fn shutdown() {
+    let Some(Sink { points, writer }) = SINK.lock().unwrap().take() else {
+        return;
+    };
+
+    // closing the channel makes the writer flush what's left and stop
+    drop(points);
+
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, writer)
+        .await
+        .is_err()
+    {
+        tracing::warn!("gave up writing the last stats to influxdb");
+    }
+}
+
+/// Queue a stats row to be written to the sink, if one is configured.
+pub fn mirror(tracker: &Thing, video: &str, stats: &Stats, timestamp: Timestamp, flagged: bool) {
+    let sink = SINK.lock().unwrap();
+    let Some(sink) = sink.as_ref() else {
+        return;
+    };
+
+    match sink
+        .points
+        .try_send(line(tracker, video, stats, timestamp, flagged))
+    {
+        Ok(()) => {}
+        Err(TrySendError::Full(_)) => {
+            tracing::warn!(%tracker, "influxdb sink is falling behind, dropping point")
+        }
+        Err(TrySendError::Closed(_)) => {
+            tracing::error!(%tracker, "influxdb sink is no longer running")
+        }
+    }
+}
+
+/// Format a stats row as an InfluxDB line protocol point.
+fn line(
+    tracker: &Thing,
+    video: &str,
+    stats: &Stats,
+    timestamp: Timestamp,
+    flagged: bool,
+) -> String {
+    let tracker = escape(&tracker.to_string());
+    let video = escape(video);
+    let timestamp = timestamp.timestamp_nanos_opt().unwrap_or_default();
+
+    format!(
+        "stats,tracker={tracker},video={video} views={}i,likes={}i,flagged={flagged} {timestamp}",
+        stats.views, stats.likes
+    )
+}
+
+fn escape(tag: &str) -> String {
+    tag.replace(',', r"\,")
+        .replace('=', r"\=")
+        .replace(' ', r"\ ")
+}
+
+struct Writer {
+    client: reqwest::Client,
+    url: Url,
+    token: Option<String>,
+}
+
+impl Writer {
+    async fn run(self, mut points: Receiver<String>) {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+
+        loop {
+            select! {
+                point = points.recv() => match point {
+                    Some(point) => {
+                        batch.push(point);
+
+                        if batch.len() >= BATCH_SIZE {
+                            self.flush(&mut batch).await;
+                        }
+                    }
+                    None => {
+                        self.flush(&mut batch).await;
+                        break;
+                    }
+                },
+
+                _ = flush.tick() => self.flush(&mut batch).await,
+            }
+        }
+    }
+
+    async fn flush(&self, batch: &mut Vec<String>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        let body = batch.join("\n");
+        // 1s, 2s, 4s
+        let strategy = ExponentialBackoff::from_millis(2)
+            .factor(500)
+            .max_delay(Duration::from_secs(30))
+            .map(jitter)
+            .take(3);
+
+        match Retry::spawn(strategy, || self.write(body.clone())).await {
+            Ok(()) => tracing::debug!(count = batch.len(), "wrote stats to influxdb"),
+            Err(error) => {
+                tracing::error!(%error, count = batch.len(), "could not write stats to influxdb")
+            }
+        }
+
+        batch.clear();
+    }
+
+    async fn write(&self, body: String) -> Result<(), reqwest::Error> {
+        let mut request = self.client.post(self.url.clone()).body(body);
+
+        if let Some(token) = &self.token {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Token {token}"));
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn stats_as_line_protocol() {
+        let tracker = Thing::from(("trackers", "abc"));
+        let stats = Stats {
+            views: 1_000,
+            likes: 50,
+        };
+        let timestamp = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+
+        assert_eq!(
+            line(&tracker, "dQw4w9WgXcQ", &stats, timestamp, false),
+            "stats,tracker=trackers:abc,video=dQw4w9WgXcQ views=1000i,likes=50i,flagged=false 1700000000000000000"
+        );
+    }
+
+    #[test]
+    fn tag_values_are_escaped() {
+        assert_eq!(escape("a b,c=d"), r"a\ b\,c\=d");
+    }
+}
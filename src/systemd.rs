@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+
+/// Tell systemd that the service has finished starting up.
+pub fn ready() {
+    notify(NotifyState::Ready);
+}
+
+/// Tell systemd that the service is shutting down.
+pub fn stopping() {
+    notify(NotifyState::Stopping);
+}
+
+fn notify(state: NotifyState) {
+    // does nothing when not running under systemd
+    if let Err(error) = sd_notify::notify(false, &[state]) {
+        tracing::warn!(%error, "could not notify systemd");
+    }
+}
+
+/// Sends keepalives to systemd at half the configured `WatchdogSec`, if the watchdog is enabled.
+pub struct Watchdog {
+    interval: Option<tokio::time::Interval>,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        let mut usec = 0;
+        let interval = sd_notify::watchdog_enabled(false, &mut usec).then(|| {
+            let period = Duration::from_micros(usec) / 2;
+            tracing::info!(?period, "systemd watchdog enabled");
+
+            tokio::time::interval(period)
+        });
+
+        Self { interval }
+    }
+
+    /// wait until the next keepalive is due and send it. never resolves when the watchdog is disabled.
+    pub async fn keepalive(&mut self) {
+        match &mut self.interval {
+            Some(interval) => {
+                interval.tick().await;
+                notify(NotifyState::Watchdog);
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
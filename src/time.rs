@@ -1,6 +1,9 @@
 use std::time::Duration;
 
-use chrono::Utc;
+use chrono::{LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
 use tracing::instrument;
 
 pub type Timestamp = chrono::DateTime<Utc>;
@@ -8,15 +11,125 @@ pub type Timestamp = chrono::DateTime<Utc>;
 pub type Interval = surrealdb::sql::Duration;
 
 #[instrument]
-pub fn timer(start: Timestamp, interval: Interval) -> tokio::time::Interval {
+pub fn timer(start: Timestamp, interval: Interval) -> Timer {
     let duration = duration_to_next_instant(start, interval, Utc::now());
     tracing::debug!(?duration, "will start ticking tracker in");
-    let start = tokio::time::Instant::now() + duration;
+    let first_tick = Instant::now() + duration;
     let period = *interval;
 
-    let mut timer = tokio::time::interval_at(start, period);
+    let mut timer = tokio::time::interval_at(first_tick, period);
     timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-    timer
+    Timer::Fixed {
+        timer,
+        origin: start,
+        interval,
+        last: None,
+    }
+}
+
+#[instrument]
+pub fn daily_timer(start: Timestamp, schedule: Daily) -> Timer {
+    let next = schedule.next_occurrence(start.max(Utc::now()));
+    tracing::debug!(%next, "will start ticking tracker at");
+
+    Timer::Daily { schedule, next }
+}
+
+/// sleep until the given instant, returning immediately if it has already passed.
+pub async fn sleep_until(timestamp: Timestamp) {
+    let instant = Instant::now() + (timestamp - Utc::now()).to_std().unwrap_or_default();
+    tokio::time::sleep_until(instant).await;
+}
+
+/// A single tick of a [Timer].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tick {
+    /// the instant this tick was scheduled for
+    pub at: Timestamp,
+    /// how many ticks were skipped since the previous one because it fired too late
+    pub missed: u64,
+}
+
+/// Ticks either on a fixed period or on a wall-clock time every day.
+#[derive(Debug)]
+pub enum Timer {
+    Fixed {
+        timer: tokio::time::Interval,
+        origin: Timestamp,
+        interval: Interval,
+        last: Option<Timestamp>,
+    },
+    Daily {
+        schedule: Daily,
+        next: Timestamp,
+    },
+}
+
+impl Timer {
+    /// wait for the next tick and return the instant it was scheduled for.
+    /// this stays on the schedule's grid even when the tick itself fires late.
+    pub async fn tick(&mut self) -> Tick {
+        match self {
+            Timer::Fixed {
+                timer,
+                origin,
+                interval,
+                last,
+            } => {
+                timer.tick().await;
+                let at = tick_instant(*origin, *interval, *last, Utc::now());
+                let missed = last.map_or(0, |last| missed_between(last, at, *interval));
+                *last = Some(at);
+
+                Tick { at, missed }
+            }
+            Timer::Daily { schedule, next } => {
+                sleep_until(*next).await;
+
+                let at = *next;
+                let mut missed = 0;
+
+                // missed days are skipped, same as the fixed timer
+                *next = schedule.next_occurrence(at + chrono::Duration::seconds(1));
+                while *next < Utc::now() {
+                    missed += 1;
+                    *next = schedule.next_occurrence(*next + chrono::Duration::seconds(1));
+                }
+
+                Tick { at, missed }
+            }
+        }
+    }
+}
+
+/// A wall-clock time that repeats every day in the given timezone, e.g. "23:59 in Asia/Tokyo".
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Daily {
+    pub at: NaiveTime,
+    pub timezone: Tz,
+}
+
+impl Daily {
+    /// compute the first occurrence of this schedule that happens at or after `after`.
+    pub fn next_occurrence(&self, after: Timestamp) -> Timestamp {
+        let today = after.with_timezone(&self.timezone).date_naive();
+
+        today
+            .iter_days()
+            .map(|date| self.resolve(date.and_time(self.at)))
+            .find(|occurrence| *occurrence >= after)
+            .expect("there is always a next day")
+    }
+
+    /// map a local wall-clock time to an instant, handling DST transitions:
+    /// - times repeated when the clock falls back resolve to the earlier instant.
+    /// - times skipped when the clock springs forward resolve to the same time one hour later.
+    fn resolve(&self, local: NaiveDateTime) -> Timestamp {
+        match self.timezone.from_local_datetime(&local) {
+            LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time.with_timezone(&Utc),
+            LocalResult::None => self.resolve(local + chrono::Duration::hours(1)),
+        }
+    }
 }
 
 /// compute the time until the next "interval instant" will occur.
@@ -37,6 +150,54 @@ fn duration_to_next_instant(start: Timestamp, interval: Interval, now: Timestamp
     Duration::from_secs(seconds_left as u64)
 }
 
+/// compute the most recent "interval instant" at or before `now`.
+fn last_instant(start: Timestamp, interval: Interval, now: Timestamp) -> Timestamp {
+    if start >= now {
+        return start;
+    }
+
+    let period = interval.as_millis() as i64;
+    let elapsed = (now - start).num_milliseconds();
+
+    if period == 0 {
+        return now;
+    }
+
+    start + chrono::Duration::milliseconds(elapsed - elapsed % period)
+}
+
+/// compute the instant a tick firing at `now` belongs to, always advancing past the `last` one.
+///
+/// the timer can fire slightly before its instant when the wall clock drifts from tokio's clock,
+/// which would otherwise round down onto the previous instant and overwrite its record.
+fn tick_instant(
+    start: Timestamp,
+    interval: Interval,
+    last: Option<Timestamp>,
+    now: Timestamp,
+) -> Timestamp {
+    let at = last_instant(start, interval, now);
+
+    match last {
+        Some(last) if at <= last => {
+            last + chrono::Duration::milliseconds(interval.as_millis() as i64)
+        }
+        _ => at,
+    }
+}
+
+/// compute how many interval instants lie strictly between two ticks.
+fn missed_between(last: Timestamp, current: Timestamp, interval: Interval) -> u64 {
+    let period = interval.as_millis() as i64;
+    let elapsed = (current - last).num_milliseconds();
+
+    if period == 0 || elapsed <= period {
+        return 0;
+    }
+
+    (elapsed / period - 1) as u64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,6 +222,113 @@ mod tests {
         );
     }
 
+    #[test]
+    fn late_tick_stays_on_the_grid() {
+        let scheduled = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        let interval = interval(Duration::minutes(10));
+        let now = scheduled + Duration::minutes(30) + Duration::milliseconds(350);
+
+        assert_eq!(
+            last_instant(scheduled, interval, now),
+            scheduled + Duration::minutes(30),
+            "a tick that fires late should be attributed to the instant it was scheduled for"
+        );
+    }
+
+    #[test]
+    fn early_tick_advances_past_the_last_one() {
+        let scheduled = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        let interval = interval(Duration::minutes(10));
+        let last = scheduled + Duration::minutes(20);
+        let now = scheduled + Duration::minutes(30) - Duration::milliseconds(5);
+
+        assert_eq!(
+            tick_instant(scheduled, interval, Some(last), now),
+            scheduled + Duration::minutes(30),
+            "a tick that fires early should not be attributed to the previous instant"
+        );
+    }
+
+    #[test]
+    fn consecutive_ticks_miss_nothing() {
+        let last = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        let interval = interval(Duration::minutes(10));
+
+        assert_eq!(
+            missed_between(last, last + Duration::minutes(10), interval),
+            0
+        );
+    }
+
+    #[test]
+    fn skipped_ticks_are_counted() {
+        let last = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        let interval = interval(Duration::minutes(10));
+
+        assert_eq!(
+            missed_between(last, last + Duration::minutes(40), interval),
+            3
+        );
+    }
+
+    #[test]
+    fn daily_schedule_later_today() {
+        let schedule = Daily {
+            at: NaiveTime::from_hms_opt(23, 59, 0).unwrap(),
+            timezone: chrono_tz::Asia::Tokyo,
+        };
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 3, 0, 0).unwrap();
+
+        assert_eq!(
+            schedule.next_occurrence(now),
+            Utc.with_ymd_and_hms(2024, 7, 1, 14, 59, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn daily_schedule_already_passed_today() {
+        let schedule = Daily {
+            at: NaiveTime::from_hms_opt(23, 59, 0).unwrap(),
+            timezone: chrono_tz::Asia::Tokyo,
+        };
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 15, 0, 0).unwrap();
+
+        assert_eq!(
+            schedule.next_occurrence(now),
+            Utc.with_ymd_and_hms(2024, 7, 2, 14, 59, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn daily_schedule_across_dst() {
+        let schedule = Daily {
+            at: NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+            timezone: chrono_tz::America::New_York,
+        };
+        let before = Utc.with_ymd_and_hms(2024, 3, 9, 18, 0, 0).unwrap();
+
+        assert_eq!(
+            schedule.next_occurrence(before),
+            Utc.with_ymd_and_hms(2024, 3, 10, 16, 0, 0).unwrap(),
+            "noon should stay noon in local time after the clock springs forward"
+        );
+    }
+
+    #[test]
+    fn daily_schedule_inside_dst_gap() {
+        let schedule = Daily {
+            at: NaiveTime::from_hms_opt(2, 30, 0).unwrap(),
+            timezone: chrono_tz::America::New_York,
+        };
+        let before = Utc.with_ymd_and_hms(2024, 3, 10, 5, 0, 0).unwrap();
+
+        assert_eq!(
+            schedule.next_occurrence(before),
+            Utc.with_ymd_and_hms(2024, 3, 10, 7, 30, 0).unwrap(),
+            "a skipped wall-clock time should be shifted past the gap"
+        );
+    }
+
     #[test]
     fn already_running_interval() {
         let now = Utc::now();
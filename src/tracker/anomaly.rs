@@ -0,0 +1,183 @@
+use crate::model::AnomalyKind;
+use crate::time::Timestamp;
+use crate::youtube::Stats;
+
+use super::peak::Sample;
+
+/// How many times faster than the previous rate views have to grow to count as a spike.
+const SPIKE_FACTOR: f64 = 10.0;
+
+/// Increases below this are never considered a spike, no matter how quiet the video was before.
+const SPIKE_FLOOR: u64 = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Irregularity {
+    pub kind: AnomalyKind,
+    pub previous: u64,
+    pub views: u64,
+}
+
+/// Remembers the last observed sample of a tracker and flags irregular changes between ticks.
+#[derive(Debug, Default)]
+pub struct Detector {
+    previous: Option<Sample>,
+    /// views gained per millisecond between the last two samples
+    rate: Option<f64>,
+}
+
+impl Detector {
+    pub fn new(previous: Option<Sample>) -> Self {
+        Self {
+            previous,
+            rate: None,
+        }
+    }
+
+    pub fn observe(&mut self, stats: &Stats, timestamp: Timestamp) -> Option<Irregularity> {
+        let views = stats.views;
+        let current = Sample { views, timestamp };
+
+        // the baseline is the last sample that wasn't flagged, so a glitch doesn't make the recovery look irregular
+        let Some(previous) = self.previous else {
+            self.previous = Some(current);
+            return None;
+        };
+
+        let Some(increase) = views.checked_sub(previous.views) else {
+            return Some(Irregularity {
+                kind: AnomalyKind::Drop,
+                previous: previous.views,
+                views,
+            });
+        };
+
+        // samples only a moment apart, e.g. on startup right before a tick, say nothing about the rate
+        let elapsed = (timestamp - previous.timestamp).num_milliseconds();
+        if elapsed <= 0 {
+            self.previous = Some(current);
+            return None;
+        }
+
+        // rates rather than raw increases, so a sample taken shortly before a tick doesn't make the next one look fast
+        let rate = increase as f64 / elapsed as f64;
+        let is_spike = self.rate.is_some_and(|last| {
+            last > 0.0 && increase >= SPIKE_FLOOR && rate > last * SPIKE_FACTOR
+        });
+
+        if is_spike {
+            return Some(Irregularity {
+                kind: AnomalyKind::Spike,
+                previous: previous.views,
+                views,
+            });
+        }
+
+        self.previous = Some(current);
+        self.rate = Some(rate);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn stats(views: u64) -> Stats {
+        Stats { views, likes: 0 }
+    }
+
+    fn at(seconds: i64) -> Timestamp {
+        Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap() + Duration::seconds(seconds)
+    }
+
+    fn detector(views: u64) -> Detector {
+        Detector::new(Some(Sample {
+            views,
+            timestamp: at(0),
+        }))
+    }
+
+    #[test]
+    fn first_observation_is_never_irregular() {
+        let mut detector = Detector::new(None);
+
+        assert_eq!(detector.observe(&stats(1_000), at(0)), None);
+    }
+
+    #[test]
+    fn views_going_backward_is_a_drop() {
+        let mut detector = detector(1_000);
+
+        let irregularity = detector.observe(&stats(900), at(600));
+        assert_eq!(
+            irregularity,
+            Some(Irregularity {
+                kind: AnomalyKind::Drop,
+                previous: 1_000,
+                views: 900,
+            })
+        );
+    }
+
+    #[test]
+    fn sudden_large_increase_is_a_spike() {
+        let mut detector = detector(1_000);
+
+        assert_eq!(detector.observe(&stats(2_000), at(600)), None);
+        assert_eq!(
+            detector
+                .observe(&stats(100_000), at(1_200))
+                .map(|irregularity| irregularity.kind),
+            Some(AnomalyKind::Spike)
+        );
+    }
+
+    #[test]
+    fn small_increase_is_not_a_spike() {
+        let mut detector = detector(1_000);
+
+        assert_eq!(detector.observe(&stats(1_001), at(600)), None);
+        assert_eq!(
+            detector.observe(&stats(2_000), at(1_200)),
+            None,
+            "increases below the floor should not be flagged even if they are relatively large"
+        );
+    }
+
+    #[test]
+    fn increase_after_a_flat_tick_is_not_a_spike() {
+        let mut detector = detector(1_000);
+
+        assert_eq!(detector.observe(&stats(1_000), at(600)), None);
+        assert_eq!(detector.observe(&stats(50_000), at(1_200)), None);
+    }
+
+    #[test]
+    fn full_interval_after_an_early_sample_is_not_a_spike() {
+        let mut detector = detector(1_000);
+
+        // a startup sample one second before the tick, then a full ten minutes at the same pace
+        assert_eq!(detector.observe(&stats(1_100), at(1)), None);
+        assert_eq!(detector.observe(&stats(61_100), at(601)), None);
+    }
+
+    #[test]
+    fn recovery_after_a_glitch_is_not_irregular() {
+        let mut detector = detector(1_000);
+
+        assert_eq!(detector.observe(&stats(2_000), at(600)), None);
+        assert_eq!(
+            detector
+                .observe(&stats(900_000), at(1_200))
+                .map(|irregularity| irregularity.kind),
+            Some(AnomalyKind::Spike)
+        );
+        assert_eq!(
+            detector.observe(&stats(3_000), at(1_800)),
+            None,
+            "the glitch should not become the baseline the next sample is compared against"
+        );
+    }
+}
@@ -1,13 +1,21 @@
 use crate::error::ApplicationError;
+use crate::systemd;
 use crate::youtube::YouTube;
 
 mod task;
 
+mod anomaly;
+mod peak;
+mod premiere;
+mod summary;
+
 mod recorder;
 mod watcher;
 
 pub async fn watcher(youtube: YouTube) -> Result<(), ApplicationError> {
     let (state, tracker_events) = watcher::get_trackers().await?;
+    systemd::ready();
+
     watcher::manage_trackers(state, tracker_events, youtube).await;
 
     Ok(())
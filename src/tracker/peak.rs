@@ -0,0 +1,81 @@
+use crate::time::Timestamp;
+
+/// Views milestone whose crossing time is kept as a per-video record.
+pub const MILLION: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub views: u64,
+    pub timestamp: Timestamp,
+}
+
+/// Samples closer together than this don't count toward a peak, e.g. a startup sample right before a tick,
+/// since a few views over a few hundred milliseconds extrapolate to millions per hour.
+const MIN_SPAN_MS: i64 = 60_000;
+
+/// Average views gained per hour between two samples.
+pub fn views_per_hour(previous: &Sample, current: &Sample) -> Option<u64> {
+    let elapsed = (current.timestamp - previous.timestamp).num_milliseconds();
+    let gained = current.views.checked_sub(previous.views)?;
+
+    if elapsed < MIN_SPAN_MS {
+        return None;
+    }
+
+    let per_hour = gained as u128 * 3_600_000 / elapsed as u128;
+    u64::try_from(per_hour).ok()
+}
+
+/// Whether `milestone` was reached in between two samples.
+pub fn crossed(previous: &Sample, current: &Sample, milestone: u64) -> bool {
+    previous.views < milestone && current.views >= milestone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, Utc};
+
+    fn sample(views: u64, timestamp: Timestamp) -> Sample {
+        Sample { views, timestamp }
+    }
+
+    #[test]
+    fn views_per_hour_scales_to_an_hour() {
+        let now = Utc::now();
+        let previous = sample(1_000, now - Duration::minutes(10));
+        let current = sample(2_000, now);
+
+        assert_eq!(views_per_hour(&previous, &current), Some(6_000));
+    }
+
+    #[test]
+    fn views_per_hour_ignores_drops() {
+        let now = Utc::now();
+        let previous = sample(2_000, now - Duration::minutes(10));
+        let current = sample(1_000, now);
+
+        assert_eq!(views_per_hour(&previous, &current), None);
+    }
+
+    #[test]
+    fn views_per_hour_ignores_samples_a_moment_apart() {
+        let now = Utc::now();
+        let previous = sample(1_000, now - Duration::milliseconds(100));
+        let current = sample(1_300, now);
+
+        assert_eq!(views_per_hour(&previous, &current), None);
+    }
+
+    #[test]
+    fn crossing_requires_previous_below_milestone() {
+        let now = Utc::now();
+        let below = sample(MILLION - 1, now);
+        let at = sample(MILLION, now);
+        let above = sample(MILLION + 1, now);
+
+        assert!(crossed(&below, &at, MILLION));
+        assert!(!crossed(&at, &above, MILLION));
+    }
+}
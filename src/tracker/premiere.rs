@@ -0,0 +1,60 @@
+use crate::time::Timestamp;
+
+/// How often an upcoming premiere is checked for a new start time.
+pub const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+/// The new start of a tracker whose video premieres at `premiere`, if it should move.
+///
+/// A tracker follows its premiere when it was scheduled on the previously `known` premiere time,
+/// or when the premiere was pushed past the scheduled start, which would only record zeros.
+pub fn reschedule(
+    scheduled_on: Timestamp,
+    known: Option<Timestamp>,
+    premiere: Timestamp,
+) -> Option<Timestamp> {
+    let follows = known == Some(scheduled_on) || premiere > scheduled_on;
+
+    (follows && premiere != scheduled_on).then_some(premiere)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn scheduled() -> Timestamp {
+        Utc.with_ymd_and_hms(2024, 7, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn unchanged_premiere_keeps_schedule() {
+        assert_eq!(reschedule(scheduled(), None, scheduled()), None);
+        assert_eq!(
+            reschedule(scheduled(), Some(scheduled()), scheduled()),
+            None
+        );
+    }
+
+    #[test]
+    fn delayed_premiere_moves_schedule() {
+        let delayed = scheduled() + Duration::hours(1);
+
+        assert_eq!(reschedule(scheduled(), None, delayed), Some(delayed));
+    }
+
+    #[test]
+    fn earlier_premiere_only_moves_a_schedule_that_followed_it() {
+        let earlier = scheduled() - Duration::hours(1);
+
+        assert_eq!(
+            reschedule(scheduled(), None, earlier),
+            None,
+            "a tracker scheduled after the premiere on purpose should stay put"
+        );
+        assert_eq!(
+            reschedule(scheduled(), Some(scheduled()), earlier),
+            Some(earlier)
+        );
+    }
+}
@@ -1,27 +1,283 @@
-use crate::model::{log, Record, Tracker};
+use crate::model::{
+    events, log, Accuracy, Anomaly, FirstDayReport, Peak, Record, Tracker, TrackerData,
+    TrackerEventKind, TrackerHealth, TrackerStatus, Video,
+};
+use crate::sink;
 use crate::time::Timestamp;
-use crate::youtube::Stats;
+use crate::youtube::{Stats, UploadInfo, YouTube};
 
+use super::anomaly::Irregularity;
+use super::peak::{self, Sample};
+use super::premiere;
+use super::summary;
 use super::watcher::TrackerId;
 
-pub async fn record_stats(tracker: &TrackerId, stats: Stats, timestamp: Timestamp) {
-    tracing::debug!(%tracker, ?stats, "recording stats");
+pub async fn record_stats(
+    tracker: &TrackerId,
+    video: &str,
+    stats: Stats,
+    timestamp: Timestamp,
+    lateness_ms: Option<u64>,
+    watch_hours: Option<u64>,
+    flagged: bool,
+) -> Option<Record> {
+    tracing::debug!(%tracker, ?stats, ?lateness_ms, flagged, "recording stats");
 
-    if let Err(err) = Record::create(tracker, stats.views, stats.likes, timestamp).await {
-        tracing::error!(%tracker, ?stats, "failed to record stats: {}", err);
+    let record = Record::create(
+        tracker,
+        stats.views,
+        stats.likes,
+        timestamp,
+        lateness_ms,
+        watch_hours,
+        flagged,
+    );
 
-        let message = format!("{err}");
-        log::error(message, tracker.clone());
+    let record = match record.await {
+        Ok(record) => record.0,
+        Err(err) => {
+            tracing::error!(%tracker, ?stats, "failed to record stats: {}", err);
+
+            let message = format!("{err}");
+            log::error(message, tracker.clone());
+            return None;
+        }
+    };
+
+    sink::mirror(tracker, video, &stats, timestamp, flagged);
+    Some(record)
+}
+
+pub async fn confirm_stats(tracker: &TrackerId, record: &Record, timestamp: Timestamp) {
+    tracing::debug!(%tracker, %record.id, "stats are unchanged, confirming previous record");
+
+    if let Err(err) = Record::confirm(&record.id, timestamp).await {
+        tracing::error!(%tracker, %record.id, "failed to confirm record: {}", err);
     }
 }
 
-pub async fn stop_tracker(tracker: &TrackerId) {
-    tracing::info!(%tracker, "stopping tracker");
+/// write the first day report unless it already exists, returning whether it exists afterwards.
+pub async fn record_first_day(tracker: &TrackerId, stats: &Stats, timestamp: Timestamp) -> bool {
+    match FirstDayReport::find(tracker).await {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            tracing::debug!(%tracker, "first day report already exists");
+            return true;
+        }
+        Err(err) => {
+            tracing::error!(%tracker, "failed to check first day report: {}", err);
+            return false;
+        }
+    }
+
+    tracing::info!(%tracker, ?stats, "recording first day report");
+
+    match FirstDayReport::create(tracker, stats.views, stats.likes, timestamp).await {
+        Ok(_) => true,
+        Err(err) => {
+            tracing::error!(%tracker, ?stats, "failed to record first day report: {}", err);
+
+            let message = format!("could not record first day report: {err}");
+            log::error(message, tracker.clone());
+            false
+        }
+    }
+}
+
+pub async fn start_tracker(tracker: &TrackerId) {
+    match Tracker::start(tracker).await {
+        Ok(Some(_)) => events::append(tracker.clone(), TrackerEventKind::Started),
+        Ok(None) => (),
+        Err(err) => tracing::error!(%tracker, "failed to mark tracker as running: {}", err),
+    }
+}
+
+pub async fn stop_tracker(tracker: &TrackerId, video: &str, status: TrackerStatus, reason: String) {
+    tracing::info!(%tracker, ?status, reason, "stopping tracker");
+
+    let length = match Video::find(video).await {
+        Ok(video) => video.map(|video| *video.info.duration),
+        Err(err) => {
+            tracing::error!(%tracker, video, "failed to get video metadata: {}", err);
+            None
+        }
+    };
 
-    if let Err(err) = Tracker::stop(tracker).await {
+    let summary = match Record::all(tracker, false).await {
+        Ok(records) => summary::summarize(&records, length),
+        Err(err) => {
+            tracing::error!(%tracker, "failed to summarize tracker: {}", err);
+            None
+        }
+    };
+
+    match Accuracy::tracker(tracker).await {
+        Ok(Some(accuracy)) => tracing::info!(%tracker, ?accuracy, "tracker accuracy"),
+        Ok(None) => (),
+        Err(err) => tracing::error!(%tracker, "failed to compute tracker accuracy: {}", err),
+    }
+
+    if let Err(err) = Tracker::stop(tracker, status, reason, summary).await {
         tracing::error!(%tracker, "failed to stop tracker: {}", err);
 
         let message = format!("could not stop tracker: {err}");
         log::error(message, tracker.clone());
     }
 }
+
+pub async fn report_accuracy() {
+    match Accuracy::overall().await {
+        Ok(Some(accuracy)) => tracing::info!(?accuracy, "overall tracker accuracy"),
+        Ok(None) => (),
+        Err(err) => tracing::error!("failed to compute overall tracker accuracy: {}", err),
+    }
+
+    match Accuracy::active().await {
+        Ok(reports) => {
+            for accuracy in reports {
+                if let Some(tracker) = &accuracy.tracker {
+                    tracing::info!(%tracker, ?accuracy, "tracker accuracy");
+                }
+            }
+        }
+        Err(err) => tracing::error!("failed to compute tracker accuracy: {}", err),
+    }
+}
+
+pub async fn first_record(tracker: &TrackerId) -> Option<Record> {
+    match Record::first(tracker).await {
+        Ok(record) => record,
+        Err(err) => {
+            tracing::error!(%tracker, "failed to get first stats: {}", err);
+            None
+        }
+    }
+}
+
+pub async fn latest_record(tracker: &TrackerId) -> Option<Record> {
+    match Record::latest(tracker).await {
+        Ok(record) => record,
+        Err(err) => {
+            tracing::error!(%tracker, "failed to get latest stats: {}", err);
+            None
+        }
+    }
+}
+
+pub async fn record_anomaly(tracker: &TrackerId, irregularity: Irregularity, timestamp: Timestamp) {
+    tracing::warn!(%tracker, ?irregularity, "detected irregular views");
+
+    let Irregularity {
+        kind,
+        previous,
+        views,
+    } = irregularity;
+
+    if let Err(err) = Anomaly::create(tracker, kind, previous, views, timestamp).await {
+        tracing::error!(%tracker, ?kind, "failed to record anomaly: {}", err);
+
+        let message = format!("could not record anomaly: {err}");
+        log::error(message, tracker.clone());
+    }
+}
+
+pub async fn record_peaks(
+    tracker: &TrackerId,
+    data: &TrackerData,
+    previous: &Sample,
+    current: &Sample,
+) {
+    let video = data.video.as_str();
+
+    if let Some(views_per_hour) = peak::views_per_hour(previous, current) {
+        if let Err(err) = Peak::views_per_hour(video, views_per_hour, current.timestamp).await {
+            tracing::error!(%tracker, video, views_per_hour, "failed to record peak views per hour: {}", err);
+        }
+    }
+
+    if peak::crossed(previous, current, peak::MILLION) {
+        let live_at = match Video::find(video).await {
+            Ok(Some(video)) => video.info.premiere_at.unwrap_or(video.info.published_at),
+            Ok(None) => return,
+            Err(err) => {
+                tracing::error!(%tracker, video, "failed to get video metadata: {}", err);
+                return;
+            }
+        };
+
+        // a tracker that started late can't tell how long the first million actually took
+        if data.scheduled_on > live_at {
+            return;
+        }
+
+        let Ok(after) = (current.timestamp - live_at).to_std() else {
+            return;
+        };
+
+        tracing::info!(%tracker, video, ?after, "video reached a million views");
+
+        if let Err(err) = Peak::million(video, after.into()).await {
+            tracing::error!(%tracker, video, "failed to record time to a million views: {}", err);
+        }
+    }
+}
+
+pub async fn record_missed_ticks(tracker: &TrackerId, missed: u64, timestamp: Timestamp) {
+    tracing::warn!(%tracker, missed, "tracker skipped ticks because it fell behind");
+
+    if let Err(err) = TrackerHealth::missed_ticks(tracker, missed, timestamp).await {
+        tracing::error!(%tracker, missed, "failed to record missed ticks: {}", err);
+    }
+}
+
+pub async fn save_video(tracker: &TrackerId, video: &str, info: UploadInfo) -> bool {
+    match Video::save(video, info).await {
+        Ok(_) => true,
+        Err(err) => {
+            tracing::error!(%tracker, video, "failed to save video metadata: {}", err);
+            false
+        }
+    }
+}
+
+pub async fn find_video(tracker: &TrackerId, video: &str) -> Option<Video> {
+    match Video::find(video).await {
+        Ok(video) => video,
+        Err(err) => {
+            tracing::error!(%tracker, video, "failed to get video metadata: {}", err);
+            None
+        }
+    }
+}
+
+/// move a tracker that hasn't started yet along with the premiere of its video.
+/// returns the premiere time that was seen, to compare the next check against.
+pub async fn check_premiere(
+    tracker: &TrackerId,
+    data: &TrackerData,
+    known: Option<Timestamp>,
+    youtube: &YouTube,
+) -> Option<Timestamp> {
+    let premiere_at = match youtube.upload_info(&data.video).await {
+        Ok(info) => info.premiere_at?,
+        Err(err) => {
+            tracing::error!(%tracker, "failed to check premiere time: {}", err);
+            return known;
+        }
+    };
+
+    let Some(scheduled_on) = premiere::reschedule(data.scheduled_on, known, premiere_at) else {
+        return Some(premiere_at);
+    };
+
+    tracing::info!(%tracker, from = %data.scheduled_on, to = %scheduled_on, "premiere moved, rescheduling tracker");
+
+    if let Err(err) = Tracker::reschedule(tracker, scheduled_on).await {
+        tracing::error!(%tracker, "failed to reschedule tracker: {}", err);
+
+        let message = format!("could not follow premiere to {scheduled_on}: {err}");
+        log::error(message, tracker.clone());
+    }
+
+    Some(premiere_at)
+}
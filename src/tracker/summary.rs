@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use crate::model::{Record, Summary};
+
+use super::peak::{self, Sample};
+
+/// Summarize the recorded stats of a tracker, ordered from oldest to newest.
+/// `length` is the duration of the tracked video, when it is known.
+pub fn summarize(records: &[Record], length: Option<Duration>) -> Option<Summary> {
+    let first = records.first()?;
+    let last = records.last()?;
+
+    let samples: Vec<Sample> = records.iter().map(sample).collect();
+    let peak_views_per_hour = samples
+        .windows(2)
+        .filter_map(|pair| peak::views_per_hour(&pair[0], &pair[1]))
+        .max();
+
+    let duration = (sample(last).timestamp - sample(first).timestamp)
+        .to_std()
+        .unwrap_or_default();
+
+    Some(Summary {
+        views: last.views,
+        likes: last.likes,
+        samples: records.len() as u64,
+        duration: duration.into(),
+        peak_views_per_hour,
+        watch_hours: length
+            .map(|length| watch_hours(last.views.saturating_sub(first.views), length)),
+    })
+}
+
+/// Estimated hours spent watching a video of `length` over `views`, as if every view watched all of it.
+pub fn watch_hours(views: u64, length: Duration) -> u64 {
+    let seconds = views as u128 * length.as_secs() as u128;
+    u64::try_from(seconds / 3600).unwrap_or(u64::MAX)
+}
+
+fn sample(record: &Record) -> Sample {
+    Sample {
+        views: record.views,
+        timestamp: record.fetched_at(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use surrealdb::sql::Thing;
+
+    fn record(views: u64, minutes: i64) -> Record {
+        let start = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+
+        Record {
+            id: Thing::from(("records", minutes.to_string().as_str())),
+            tracker: Thing::from(("trackers", "abc")),
+            views,
+            likes: views / 10,
+            tick_at: Some(start + Duration::minutes(minutes)),
+            flagged: false,
+            confirmed_at: None,
+            lateness_ms: None,
+            watch_hours: None,
+            created_at: start,
+        }
+    }
+
+    #[test]
+    fn nothing_to_summarize() {
+        assert_eq!(summarize(&[], None), None);
+    }
+
+    #[test]
+    fn summary_of_recorded_stats() {
+        let records = [
+            record(0, 0),
+            record(1_000, 10),
+            record(5_000, 20),
+            record(6_000, 30),
+        ];
+
+        let summary = summarize(&records, None).unwrap();
+        assert_eq!(summary.views, 6_000);
+        assert_eq!(summary.likes, 600);
+        assert_eq!(summary.samples, 4);
+        assert_eq!(*summary.duration, std::time::Duration::from_secs(30 * 60));
+        assert_eq!(
+            summary.peak_views_per_hour,
+            Some(24_000),
+            "the fastest ten minutes gained 4000 views"
+        );
+    }
+
+    #[test]
+    fn samples_a_moment_apart_are_not_a_peak() {
+        let mut early = record(1_000, 0);
+        let mut tick = record(1_300, 0);
+        early.tick_at = tick
+            .tick_at
+            .map(|tick_at| tick_at - Duration::milliseconds(100));
+        tick.lateness_ms = Some(0);
+
+        let summary = summarize(&[early, tick, record(2_300, 10)], None).unwrap();
+        assert_eq!(
+            summary.peak_views_per_hour,
+            Some(6_000),
+            "300 views in 100ms should not count as 10 million per hour"
+        );
+    }
+
+    #[test]
+    fn watch_hours_of_tracked_views() {
+        let records = [record(1_000, 0), record(7_000, 60)];
+        let length = std::time::Duration::from_secs(4 * 60);
+
+        let summary = summarize(&records, Some(length)).unwrap();
+        assert_eq!(
+            summary.watch_hours,
+            Some(400),
+            "6000 views of a four minute video"
+        );
+    }
+}
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+
 use chrono::Utc;
 use dashmap::DashMap;
 use futures::{Future, FutureExt, StreamExt};
@@ -6,20 +9,43 @@ use surrealdb::sql::Thing;
 use surrealdb::Action;
 use tokio::select;
 use tokio::sync::mpsc::UnboundedReceiver;
-use tracing::instrument;
+use tracing::{instrument, Instrument};
 
-use crate::database::database;
+use crate::database::{database, query::Valid};
 use crate::error::{ActiveTrackersSnafu, ApplicationError, WatchTrackersSnafu};
-use crate::model::{log, Tracker, TrackerData};
-use crate::time;
-use crate::youtube::YouTube;
+use crate::maintenance;
+use crate::model::{events, log, Record, Tracker, TrackerData, TrackerEventKind, TrackerStatus};
+use crate::systemd::Watchdog;
+use crate::time::{self, Timestamp};
+use crate::youtube::{Fetched, YouTube, YouTubeError};
+
+use super::anomaly::Detector;
+use super::peak::Sample;
+use super::premiere;
 
 pub type TrackerId = Thing;
 
+/// How often running tasks are checked against the trackers that are still active.
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// How often the accuracy report is logged.
+const ACCURACY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How often the cached metadata of a tracked video is refreshed.
+const VIDEO_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
 pub(super) enum Event {
-    Add { tracker: Tracker },
-    Update { id: TrackerId, data: TrackerData },
-    Stop { id: TrackerId },
+    Add {
+        tracker: Tracker,
+    },
+    Update {
+        id: TrackerId,
+        data: TrackerData,
+    },
+    Stop {
+        id: TrackerId,
+        status: Option<TrackerStatus>,
+    },
 }
 
 pub(super) type State = DashMap<TrackerId, Task>;
@@ -29,7 +55,7 @@ pub(super) async fn get_trackers() -> Result<(State, UnboundedReceiver<Event>),
 
     let state = DashMap::new();
 
-    let active_trackers = Tracker::all_active().await.context(ActiveTrackersSnafu)?;
+    let Valid(active_trackers) = Tracker::all_active().await.context(ActiveTrackersSnafu)?;
     tracing::info!(count = active_trackers.len(), "found active trackers");
 
     for tracker in active_trackers {
@@ -61,8 +87,12 @@ pub(super) async fn get_trackers() -> Result<(State, UnboundedReceiver<Event>),
 
             match action {
                 Action::Update if tracker.is_stopped() => {
-                    tx.send(Event::Stop { id: tracker.id })
-                        .expect("send stop event");
+                    let event = Event::Stop {
+                        id: tracker.id,
+                        status: Some(tracker.status),
+                    };
+
+                    tx.send(event).expect("send stop event");
                 }
                 Action::Update => {
                     let event = Event::Update {
@@ -73,11 +103,19 @@ pub(super) async fn get_trackers() -> Result<(State, UnboundedReceiver<Event>),
                     tx.send(event).expect("send update event");
                 }
                 Action::Create => {
-                    tx.send(Event::Add { tracker }).expect("send add event");
+                    events::append(tracker.id.clone(), TrackerEventKind::Created);
+
+                    if !tracker.is_stopped() {
+                        tx.send(Event::Add { tracker }).expect("send add event");
+                    }
                 }
                 Action::Delete => {
-                    tx.send(Event::Stop { id: tracker.id })
-                        .expect("send stop event");
+                    let event = Event::Stop {
+                        id: tracker.id,
+                        status: None,
+                    };
+
+                    tx.send(event).expect("send stop event");
                 }
 
                 _ => (),
@@ -93,15 +131,83 @@ pub(super) async fn manage_trackers(
     mut trackers: UnboundedReceiver<Event>,
     youtube: YouTube,
 ) {
-    while let Some(event) = trackers.recv().await {
-        match event {
-            Event::Add { tracker } => add_tracker(&state, youtube.clone(), tracker),
-            Event::Update { id, data } => update_tracker(&state, youtube.clone(), &id, data),
-            Event::Stop { id } => remove_tracker(&state, &id),
+    let mut watchdog = Watchdog::new();
+    let mut sweep_interval = tokio::time::interval(SWEEP_INTERVAL);
+
+    // it scans every record, so it runs on its own instead of holding up events and keepalives
+    tokio::spawn(report_accuracy().in_current_span());
+
+    loop {
+        select! {
+            event = trackers.recv() => match event {
+                Some(Event::Add { tracker }) => add_tracker(&state, youtube.clone(), tracker),
+                Some(Event::Update { id, data }) => update_tracker(&state, youtube.clone(), &id, data),
+                Some(Event::Stop { id, status }) => remove_tracker(&state, &id, status),
+                None => break,
+            },
+
+            _ = watchdog.keepalive() => (),
+
+            // the database may be down for maintenance, which would only fill the logs with errors
+            _ = sweep_interval.tick(), if maintenance::during(Utc::now()).is_none() => sweep(&state).await,
+        }
+    }
+}
+
+/// log how far behind schedule stats are fetched, overall and for every active tracker.
+async fn report_accuracy() {
+    let mut interval = tokio::time::interval(ACCURACY_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if maintenance::during(Utc::now()).is_none() {
+            super::recorder::report_accuracy().await;
         }
     }
 }
 
+/// drop tasks whose tracker is no longer active, in case its stop event was missed, and tasks that have ended.
+async fn sweep(state: &State) {
+    let statuses: HashMap<String, TrackerStatus> = match Tracker::statuses().await {
+        Ok(trackers) => trackers
+            .into_iter()
+            .map(|tracker| (tracker.id.to_string(), tracker.status))
+            .collect(),
+        Err(error) => {
+            tracing::error!(%error, "could not get tracker statuses to sweep tasks");
+            return;
+        }
+    };
+
+    let mut stale = Vec::new();
+
+    for task in state.iter() {
+        match statuses.get(&task.key().to_string()).copied() {
+            Some(status) if status.is_active() => {
+                if task.handle.is_finished() {
+                    stale.push((task.key().clone(), None));
+                }
+            }
+            // the stop event of this tracker was missed, so its event is appended here instead
+            status => stale.push((task.key().clone(), TrackerEventKind::stopped(status))),
+        }
+    }
+
+    for (id, kind) in stale {
+        if let Some((id, task)) = state.remove(&id) {
+            tracing::info!(tracker.id = %id, "swept stale tracker task");
+            task.stop();
+
+            if let Some(kind) = kind {
+                events::append(id, kind);
+            }
+        }
+    }
+
+    tracing::info!(tasks = state.len(), "swept tracker tasks");
+}
+
 #[instrument(skip(youtube, state))]
 fn add_tracker(state: &State, youtube: YouTube, tracker: Tracker) {
     tracing::info!(%tracker.id, "received add tracker event");
@@ -111,12 +217,16 @@ fn add_tracker(state: &State, youtube: YouTube, tracker: Tracker) {
     state.insert(tracker.id, task);
 }
 
-fn remove_tracker(state: &State, id: &TrackerId) {
+fn remove_tracker(state: &State, id: &TrackerId, status: Option<TrackerStatus>) {
     tracing::info!(%id, "received stop tracker event");
 
     if let Some((id, task)) = state.remove(id) {
         tracing::debug!(tracker.id = %id, "stopping tracker");
         task.stop();
+
+        if let Some(kind) = TrackerEventKind::stopped(status) {
+            events::append(id, kind);
+        }
     };
 }
 
@@ -124,70 +234,268 @@ fn remove_tracker(state: &State, id: &TrackerId) {
 fn update_tracker(state: &State, youtube: YouTube, id: &TrackerId, data: TrackerData) {
     tracing::info!(%id, "received update tracker event");
 
-    let Some((id, old_task)) = state.remove(id) else {
-        tracing::error!(tracker.id = %id, tracker.data = ?data, "tried to update a tracker but it cannot be found");
+    if state.get(id).is_some_and(|task| task.data == data) {
+        tracing::debug!(tracker.id = %id, "tracker data is unchanged, keep it running");
         return;
-    };
+    }
 
-    old_task.stop();
-    tracing::info!(tracker.id = %id, tracker.data = ?data, "updated tracker");
+    match state.remove(id) {
+        Some((_, old_task)) => {
+            let kind = if old_task.data.same_schedule(&data) {
+                TrackerEventKind::Updated
+            } else {
+                TrackerEventKind::Rescheduled
+            };
+
+            old_task.stop();
+            tracing::info!(tracker.id = %id, tracker.data = ?data, "updated tracker");
+            events::append(id.clone(), kind);
+        }
+        None => {
+            tracing::info!(tracker.id = %id, tracker.data = ?data, "resumed tracker");
+            events::append(id.clone(), TrackerEventKind::Resumed);
+        }
+    }
 
     let task = run_tracker(id.clone(), data, youtube);
     state.insert(id.clone(), task);
 }
 
 pub(super) struct Task {
-    _handle: tokio::task::JoinHandle<()>,
+    handle: tokio::task::JoinHandle<()>,
     stop: tokio::sync::oneshot::Sender<()>,
+    data: TrackerData,
 }
 
 impl Task {
     fn new(
         stop: tokio::sync::oneshot::Sender<()>,
+        data: TrackerData,
         f: impl Future<Output = ()> + Send + 'static,
     ) -> Self {
         Self {
-            _handle: tokio::spawn(f),
+            // keep the span of whoever started the task, so its records show up under it
+            handle: tokio::spawn(f.in_current_span()),
             stop,
+            data,
         }
     }
 
     fn stop(self) {
-        self.stop.send(()).expect("send stop signal");
+        // the task may already be gone, e.g. when it is swept after ending
+        if self.stop.send(()).is_err() {
+            tracing::debug!("tracker task has already ended");
+        }
     }
 }
 
 #[instrument(skip(youtube))]
 fn run_tracker(id: TrackerId, tracker: TrackerData, youtube: YouTube) -> Task {
     let (stop, mut signal) = tokio::sync::oneshot::channel();
+    let youtube = match &tracker.instance {
+        Some(instance) => youtube.with_instance(instance),
+        None => youtube,
+    };
 
-    Task::new(stop, async move {
-        let mut timer = time::timer(tracker.scheduled_on, tracker.interval);
-
-        record(&id, &tracker, &youtube).await;
+    Task::new(stop, tracker.clone(), async move {
+        let mut restarts = 0;
 
         loop {
+            let started_at = std::time::Instant::now();
+            let run = AssertUnwindSafe(track(&id, &tracker, &youtube, &mut signal));
+
+            let Err(panic) = run.catch_unwind().await else {
+                break;
+            };
+
+            // a tracker that ran cleanly for a full period is back to a short backoff
+            if started_at.elapsed() >= tracker.period() {
+                restarts = 0;
+            }
+
+            restarts += 1;
+            let backoff = restart_backoff(restarts);
+            let reason = panic_message(panic.as_ref());
+            tracing::error!(tracker.id = %id, reason, restarts, ?backoff, "tracker panicked, restarting");
+
+            let message = format!("tracker panicked, restarting in {backoff:?}: {reason}");
+            log::error(message, id.clone());
+
             select! {
-                _ = &mut signal => {
-                    tracing::info!(tracker.id = %id, "stopped tracker");
-                    break;
+                _ = &mut signal => break,
+                _ = tokio::time::sleep(backoff) => (),
+            }
+        }
+    })
+}
+
+/// Upper bound of the delay before restarting a tracker that keeps panicking.
+const MAX_RESTART_BACKOFF: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// wait 1s, 2s, 4s, ... up to [MAX_RESTART_BACKOFF] before restarting a tracker that panicked `restarts` times.
+fn restart_backoff(restarts: u32) -> std::time::Duration {
+    let backoff =
+        std::time::Duration::from_secs(1).saturating_mul(2u32.saturating_pow(restarts - 1));
+    backoff.min(MAX_RESTART_BACKOFF)
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// run a tracker until it receives the stop signal.
+async fn track(
+    id: &TrackerId,
+    tracker: &TrackerData,
+    youtube: &YouTube,
+    signal: &mut tokio::sync::oneshot::Receiver<()>,
+) {
+    let mut timer = match &tracker.daily {
+        Some(daily) => time::daily_timer(tracker.scheduled_on, daily.clone()),
+        None => time::timer(tracker.scheduled_on, tracker.interval),
+    };
+    // nothing can be loaded while the database is down for maintenance
+    if let Some(window) = maintenance::during(Utc::now()) {
+        tracing::debug!(tracker.id = %id, ?window, "waiting for maintenance to end");
+
+        select! {
+            _ = &mut *signal => return,
+            _ = time::sleep_until(window.end) => (),
+        }
+    }
+
+    let mut progress = Progress::load(id, tracker).await;
+    // end of the maintenance window a tick was skipped for, to record once it's over
+    let mut catch_up = None;
+    let mut premiere_check = tokio::time::interval(premiere::CHECK_INTERVAL);
+    let mut premiere_at = None;
+
+    // a tracker that recorded before a restart picks up on its next tick, which is still on the same grid,
+    // instead of taking an extra sample off the grid right away
+    let warm = progress.last_record.is_some();
+
+    match maintenance::during(Utc::now()) {
+        Some(window) => catch_up = Some(window.end),
+        None if warm => tracing::debug!(tracker.id = %id, "resuming tracker on its next tick"),
+        None => record(id, tracker, youtube, &mut progress, Utc::now(), false).await,
+    }
+
+    loop {
+        select! {
+            _ = &mut *signal => {
+                tracing::info!(tracker.id = %id, "stopped tracker");
+                break;
+            }
+
+            _ = premiere_check.tick(), if Utc::now() < tracker.scheduled_on && maintenance::during(Utc::now()).is_none() => {
+                premiere_at = super::recorder::check_premiere(id, tracker, premiere_at, youtube).await;
+            }
+
+            _ = time::sleep_until(catch_up.unwrap_or_default()), if catch_up.is_some() => {
+                tracing::debug!(tracker.id = %id, "catching up after maintenance");
+                catch_up = None;
+
+                record(id, tracker, youtube, &mut progress, Utc::now(), false).await;
+            }
+
+            tick = timer.tick() => {
+                tracing::debug!(tracker.id = %id, timestamp = ?tick.at, "tracker ticked");
+
+                if let Some(window) = maintenance::during(tick.at) {
+                    tracing::debug!(tracker.id = %id, ?window, "skipping tick during maintenance");
+                    catch_up = Some(window.end);
+                    continue;
                 }
 
-                time = timer.tick() => {
-                    tracing::debug!(tracker.id = %id, timestamp = ?time, "tracker ticked");
+                catch_up = None;
 
-                    record(&id, &tracker, &youtube).await;
+                if tick.missed > 0 {
+                    super::recorder::record_missed_ticks(id, tick.missed, tick.at).await;
                 }
+
+                record(id, tracker, youtube, &mut progress, tick.at, true).await;
             }
         }
-    })
+    }
 }
 
-async fn record(id: &TrackerId, tracker: &TrackerData, youtube: &YouTube) {
-    let now = Utc::now();
+/// State carried over between ticks of a running tracker.
+struct Progress {
+    detector: Detector,
+    previous: Option<Sample>,
+    last_record: Option<Record>,
+    started: bool,
+    /// whether the first day report is known to exist, to skip looking it up on every tick
+    first_day_reported: bool,
+    video_refreshed_at: Option<Timestamp>,
+    /// length of the tracked video, to estimate watch hours
+    length: Option<std::time::Duration>,
+    /// views of the first sample, which watch hours are counted from
+    first_views: Option<u64>,
+}
+
+impl Progress {
+    async fn load(id: &TrackerId, tracker: &TrackerData) -> Self {
+        let last_record = super::recorder::latest_record(id).await;
+        let first_record = super::recorder::first_record(id).await;
+        let video = super::recorder::find_video(id, &tracker.video).await;
+        let previous = last_record.as_ref().map(|record| Sample {
+            views: record.views,
+            timestamp: record.fetched_at(),
+        });
+
+        Self {
+            detector: Detector::new(previous),
+            previous,
+            last_record,
+            started: false,
+            first_day_reported: false,
+            video_refreshed_at: video.as_ref().map(|video| video.refreshed_at),
+            length: video.map(|video| *video.info.duration),
+            first_views: first_record.map(|record| record.views),
+        }
+    }
+}
+
+/// fetch and store the stats of a tracker for the tick scheduled at `tick_at`.
+/// samples taken off the grid, e.g. on startup, are `on_grid: false` and don't count toward accuracy.
+#[instrument(skip_all, fields(tracker.id = %id, %tick_at))]
+async fn record(
+    id: &TrackerId,
+    tracker: &TrackerData,
+    youtube: &YouTube,
+    progress: &mut Progress,
+    tick_at: Timestamp,
+    on_grid: bool,
+) {
+    let fetched_at = Utc::now();
+    let fetch = AssertUnwindSafe(youtube.stats_info(&tracker.video, tick_at));
 
-    let stats = match youtube.stats_info(&tracker.video).catch_unwind().await {
-        Ok(Ok(stats)) => stats,
+    let (stats, info) = match fetch.catch_unwind().await {
+        Ok(Ok(Fetched { stats, info })) => (stats, info),
+        Ok(Err(error @ YouTubeError::NotFound { .. })) => {
+            tracing::error!(%error, "video can no longer be tracked");
+
+            let reason = format!("{error}");
+            super::recorder::stop_tracker(id, &tracker.video, TrackerStatus::Failed, reason).await;
+
+            return;
+        }
+        Ok(Err(error @ YouTubeError::Restricted { .. })) => {
+            tracing::error!(%error, "video can not be watched by the invidious instance");
+
+            let reason = format!("{error}");
+            super::recorder::stop_tracker(id, &tracker.video, TrackerStatus::Restricted, reason)
+                .await;
+
+            return;
+        }
         Ok(Err(error)) => {
             tracing::error!(%error, "could not fetch video stats");
 
@@ -206,9 +514,93 @@ async fn record(id: &TrackerId, tracker: &TrackerData, youtube: &YouTube) {
         }
     };
 
-    if tracker.exceed_milestone(stats.views) {
-        super::recorder::stop_tracker(id).await;
+    let refresh_due = progress.video_refreshed_at.map_or(true, |refreshed_at| {
+        (Utc::now() - refreshed_at).to_std().unwrap_or_default() >= VIDEO_REFRESH_INTERVAL
+    });
+
+    let length = *info.duration;
+
+    if refresh_due && super::recorder::save_video(id, &tracker.video, info).await {
+        progress.video_refreshed_at = Some(Utc::now());
+    }
+
+    progress.length = Some(length);
+
+    if !progress.started && tick_at >= tracker.scheduled_on {
+        progress.started = true;
+        super::recorder::start_tracker(id).await;
+    }
+
+    // suspicious samples are still stored, but flagged and kept out of every derived figure
+    let irregularity = progress.detector.observe(&stats, fetched_at);
+    let flagged = irregularity.is_some();
+
+    if let Some(irregularity) = irregularity {
+        super::recorder::record_anomaly(id, irregularity, tick_at).await;
     }
 
-    super::recorder::record_stats(id, stats, now).await;
+    let views = stats.views;
+
+    if !flagged {
+        // timed by the fetch rather than the tick, so short spans between samples aren't skewed
+        let current = Sample {
+            views,
+            timestamp: fetched_at,
+        };
+
+        if let Some(previous) = progress.previous.replace(current) {
+            super::recorder::record_peaks(id, tracker, &previous, &current).await;
+        }
+
+        // the first good sample past the mark, so a failed or flagged tick right at it doesn't lose the report
+        if !progress.first_day_reported && tick_at >= tracker.first_day() {
+            progress.first_day_reported =
+                super::recorder::record_first_day(id, &stats, tick_at).await;
+        }
+    }
+
+    let unchanged = progress
+        .last_record
+        .as_ref()
+        .filter(|record| record.is_unchanged(&stats));
+
+    match unchanged {
+        Some(record) if tracker.skip_unchanged && tick_at >= tracker.first_day() => {
+            super::recorder::confirm_stats(id, record, tick_at).await;
+        }
+        _ => {
+            // cumulative over the tracked views, so running trackers can be compared too
+            let first_views = progress.first_views.unwrap_or(views);
+            let watch_hours = progress.length.map(|length| {
+                super::summary::watch_hours(views.saturating_sub(first_views), length)
+            });
+
+            let lateness_ms =
+                on_grid.then(|| (fetched_at - tick_at).num_milliseconds().max(0) as u64);
+
+            let record = super::recorder::record_stats(
+                id,
+                &tracker.video,
+                stats,
+                tick_at,
+                lateness_ms,
+                watch_hours,
+                flagged,
+            )
+            .await;
+
+            if !flagged && record.is_some() {
+                progress.first_views.get_or_insert(views);
+            }
+
+            if !flagged {
+                progress.last_record = record;
+            }
+        }
+    }
+
+    if !flagged && tracker.exceed_milestone(views) {
+        let reason = format!("reached {views} views");
+        super::recorder::stop_tracker(id, &tracker.video, TrackerStatus::Completed, reason).await;
+    }
 }
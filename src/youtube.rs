@@ -1,16 +1,28 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use dashmap::DashMap;
 use invidious::MethodAsync::Reqwest;
 use invidious::{ClientAsyncTrait, InvidiousError};
 use serde::{Deserialize, Serialize};
 use snafu::{OptionExt, ResultExt, Snafu};
+use tokio::sync::Mutex;
 use tokio_retry::strategy::{jitter, ExponentialBackoff};
 use tokio_retry::Retry;
 use tracing::instrument;
 
-use crate::time::Timestamp;
+use crate::time::{Interval, Timestamp};
+
+/// Stats of a video fetched less than this long ago are reused instead of fetched again.
+const FETCH_FLOOR: Duration = Duration::from_secs(30);
 
 pub async fn connect(config: &YouTubeConfig) -> YouTube {
     let invidious = invidious::ClientAsync::new(config.invidious_instance.clone(), Reqwest);
-    YouTube { invidious }
+    YouTube {
+        invidious,
+        recent: Arc::default(),
+    }
 }
 
 #[derive(Debug, Snafu, PartialEq)]
@@ -36,14 +48,62 @@ impl Default for YouTubeConfig {
     }
 }
 
+/// Last stats fetched for a video, shared by every tracker of that video.
+type RecentStats = Arc<Mutex<Option<(Timestamp, Fetched)>>>;
+
+/// Stats and metadata of a video, taken from the same response.
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    pub stats: Stats,
+    pub info: UploadInfo,
+}
+
 #[derive(Clone)]
 pub struct YouTube {
     invidious: invidious::ClientAsync,
+    recent: Arc<DashMap<String, RecentStats>>,
 }
 
 impl YouTube {
+    /// a client that fetches from another Invidious instance, e.g. one dedicated to a single campaign.
+    pub fn with_instance(&self, instance: &str) -> YouTube {
+        let invidious = invidious::ClientAsync::new(instance.to_string(), Reqwest);
+        YouTube {
+            invidious,
+            recent: self.recent.clone(),
+        }
+    }
+
     // #[instrument(skip(self))]
-    pub async fn stats_info(&self, video_id: &str) -> Result<Stats, YouTubeError> {
+    /// fetch the stats of a video for the tick at `tick_at`, at most once every [FETCH_FLOOR] across all trackers.
+    ///
+    /// stats are only reused when they were fetched after `tick_at`,
+    /// so a tracker on a different schedule never records an older count as a new sample.
+    pub async fn stats_info(
+        &self,
+        video_id: &str,
+        tick_at: Timestamp,
+    ) -> Result<Fetched, YouTubeError> {
+        let recent = self.recent.entry(video_id.to_owned()).or_default().clone();
+        // trackers of the same video wait here, so the first one fetches and the rest reuse it
+        let mut recent = recent.lock().await;
+
+        if let Some((fetched_at, fetched)) = recent.as_ref() {
+            let age = (Utc::now() - *fetched_at).to_std().unwrap_or_default();
+
+            if *fetched_at >= tick_at && age < FETCH_FLOOR {
+                tracing::debug!(video_id, "reusing recently fetched stats");
+                return Ok(fetched.clone());
+            }
+        }
+
+        let fetched = self.fetch_stats(video_id).await?;
+        *recent = Some((Utc::now(), fetched.clone()));
+
+        Ok(fetched)
+    }
+
+    async fn fetch_stats(&self, video_id: &str) -> Result<Fetched, YouTubeError> {
         tracing::info!(video_id, "fetching video");
         // let strategy = ExponentialBackoff::from_millis(1000).map(jitter).take(3);
 
@@ -58,10 +118,34 @@ impl YouTube {
         Self::get_stats(client.clone(), video_id.clone()).await
     }
 
+    pub async fn upload_info(&self, video_id: &str) -> Result<UploadInfo, YouTubeError> {
+        tracing::info!(video_id, "fetching video metadata");
+
+        let video = Self::get_video(self.invidious.clone(), video_id.to_owned()).await?;
+        Ok(UploadInfo::from(video))
+    }
+
     async fn get_stats(
         invidious: invidious::ClientAsync,
         video_id: String,
-    ) -> Result<Stats, YouTubeError> {
+    ) -> Result<Fetched, YouTubeError> {
+        let response = Self::get_video(invidious, video_id).await?;
+
+        let stats = Stats {
+            likes: response.likes.into(),
+            views: response.views,
+        };
+
+        Ok(Fetched {
+            stats,
+            info: UploadInfo::from(response),
+        })
+    }
+
+    async fn get_video(
+        invidious: invidious::ClientAsync,
+        video_id: String,
+    ) -> Result<invidious::video::Video, YouTubeError> {
         let task = tokio::task::spawn(async move {
             invidious
                 .video(&video_id, None)
@@ -69,19 +153,43 @@ impl YouTube {
                 .map_err(YouTubeError::from)
         });
 
-        let response = task.await.ok().context(JoinSnafu)??;
-
-        Ok(Stats {
-            likes: response.likes.into(),
-            views: response.views,
-        })
+        task.await.ok().context(JoinSnafu)?
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct UploadInfo {
     pub title: String,
+    pub channel: String,
+    pub channel_id: String,
+    pub duration: Interval,
     pub published_at: Timestamp,
+    pub premiere_at: Option<Timestamp>,
+    pub thumbnail: Option<String>,
+}
+
+impl From<invidious::video::Video> for UploadInfo {
+    fn from(video: invidious::video::Video) -> Self {
+        let timestamp = |seconds: u64| Utc.timestamp_opt(seconds as i64, 0).single();
+
+        let thumbnail = video
+            .thumbnails
+            .into_iter()
+            .max_by_key(|thumbnail| thumbnail.width)
+            .map(|thumbnail| thumbnail.url);
+
+        Self {
+            title: video.title,
+            channel: video.author,
+            channel_id: video.author_id,
+            duration: Duration::from_secs(video.length.into()).into(),
+            published_at: timestamp(video.published).unwrap_or_default(),
+            premiere_at: Some(video.premiere_timestamp)
+                .filter(|&seconds| seconds > 0)
+                .and_then(timestamp),
+            thumbnail,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -102,6 +210,17 @@ pub enum YouTubeError {
     #[snafu(display("The video doesn't exist or is private: {message}"))]
     NotFound { message: String },
 
+    /// The video exists, but the Invidious instance isn't allowed to watch it
+    #[snafu(display("The video is {restriction}: {message}"))]
+    Restricted {
+        restriction: Restriction,
+        message: String,
+    },
+
+    /// Invidious could not get the video right now, e.g. because YouTube is blocking the instance
+    #[snafu(display("{message}"))]
+    Unavailable { message: String },
+
     #[snafu(display("{message}"))]
     Network { message: String },
 
@@ -115,7 +234,14 @@ pub enum YouTubeError {
 impl From<InvidiousError> for YouTubeError {
     fn from(value: InvidiousError) -> Self {
         match value {
-            InvidiousError::ApiError { message } => YouTubeError::NotFound { message },
+            InvidiousError::ApiError { message } => match Restriction::from_message(&message) {
+                Some(restriction) => YouTubeError::Restricted {
+                    restriction,
+                    message,
+                },
+                None if is_gone(&message) => YouTubeError::NotFound { message },
+                None => YouTubeError::Unavailable { message },
+            },
             InvidiousError::Fetch { error } => YouTubeError::Network {
                 message: error.to_string(),
             },
@@ -127,3 +253,89 @@ impl From<InvidiousError> for YouTubeError {
         }
     }
 }
+
+/// whether the error message YouTube gave to Invidious means the video is gone for good.
+///
+/// Invidious reports instance problems (e.g. "Sign in to confirm you're not a bot") the same way,
+/// so anything that isn't known to be permanent is treated as a temporary failure.
+fn is_gone(message: &str) -> bool {
+    let message = message.to_lowercase();
+
+    ["private", "unavailable", "removed", "does not exist"]
+        .iter()
+        .any(|reason| message.contains(reason))
+}
+
+/// Why a video can't be watched, as reported by Invidious.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Restriction {
+    MembersOnly,
+    AgeRestricted,
+    RegionBlocked,
+}
+
+impl Restriction {
+    /// guess the restriction from the error message YouTube gave to Invidious.
+    fn from_message(message: &str) -> Option<Self> {
+        let message = message.to_lowercase();
+
+        if message.contains("members") || message.contains("join this channel") {
+            Some(Restriction::MembersOnly)
+        } else if message.contains("confirm your age") || message.contains("age-restricted") {
+            Some(Restriction::AgeRestricted)
+        } else if message.contains("in your country") || message.contains("not available in your") {
+            Some(Restriction::RegionBlocked)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for Restriction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Restriction::MembersOnly => write!(f, "members-only"),
+            Restriction::AgeRestricted => write!(f, "age-restricted"),
+            Restriction::RegionBlocked => {
+                write!(f, "blocked in the region of the invidious instance")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restriction_from_invidious_message() {
+        assert_eq!(
+            Restriction::from_message("Join this channel to get access to members-only content like this video, and other exclusive perks."),
+            Some(Restriction::MembersOnly)
+        );
+        assert_eq!(
+            Restriction::from_message("Sign in to confirm your age"),
+            Some(Restriction::AgeRestricted)
+        );
+        assert_eq!(
+            Restriction::from_message(
+                "The uploader has not made this video available in your country"
+            ),
+            Some(Restriction::RegionBlocked)
+        );
+        assert_eq!(Restriction::from_message("This video is private"), None);
+    }
+
+    #[test]
+    fn only_permanent_errors_mean_the_video_is_gone() {
+        assert!(is_gone("This video is private"));
+        assert!(is_gone("Video unavailable"));
+        assert!(is_gone(
+            "This video has been removed for violating YouTube's Terms of Service"
+        ));
+        assert!(!is_gone(
+            "Could not extract video info. Instance is likely blocked."
+        ));
+        assert!(!is_gone("Sign in to confirm you're not a bot"));
+    }
+}